@@ -87,6 +87,17 @@ impl Breakpoint {
         }
     }
 
+    /// Writes the original instruction byte back into every thread/process
+    /// this breakpoint was active in. Used when tracing ends early so the
+    /// tracee isn't left with a dangling interrupt. Errors are ignored as the
+    /// processes may have already exited.
+    pub fn restore(&mut self) {
+        for pid in self.is_running.keys() {
+            let _ = self.disable(*pid);
+        }
+        self.is_running.clear();
+    }
+
     /// Call this when a ptrace thread is killed. Won't reenable the breakpoint
     /// so may lose the ability to instrument this line.
     pub fn thread_killed(&mut self, pid: Pid) {
@@ -0,0 +1,39 @@
+use std::collections::HashSet;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+
+fn git(root: &Path, args: &[&str]) -> io::Result<String> {
+    let output = Command::new("git").current_dir(root)
+                                    .args(args)
+                                    .output()?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        let err = String::from_utf8_lossy(&output.stderr);
+        Err(io::Error::new(io::ErrorKind::Other, err.trim().to_string()))
+    }
+}
+
+/// Gets the root of the git repository containing `root`
+pub fn repository_root(root: &Path) -> io::Result<PathBuf> {
+    let toplevel = git(root, &["rev-parse", "--show-toplevel"])?;
+    let toplevel = PathBuf::from(toplevel.trim());
+    Ok(toplevel.canonicalize().unwrap_or(toplevel))
+}
+
+/// Gets the files changed since the base revision in the git repository
+/// containing `root`. Git lists paths relative to the repository root which
+/// may be above the workspace so they're made absolute and canonical to match
+/// the traced paths. Renamed files are listed under their new name and deleted
+/// files are left out.
+pub fn changed_files(root: &Path, base: &str) -> io::Result<HashSet<PathBuf>> {
+    let toplevel = repository_root(root)?;
+    let diff = git(root, &["diff", "--name-only", "-M", "--diff-filter=d", base])?;
+    let files = diff.lines()
+                    .filter(|l| !l.is_empty())
+                    .filter_map(|l| toplevel.join(l).canonicalize().ok())
+                    .collect();
+    Ok(files)
+}
@@ -7,6 +7,8 @@ use clap::{ArgMatches};
 use coveralls_api::{CiService};
 use regex::{Regex};
 
+use changes::repository_root;
+
 use self::parse::*;
 
 
@@ -23,20 +25,63 @@ pub struct Config {
     pub run_ignored: bool,
     /// Flag to ignore test functions in coverage statistics
     pub ignore_tests: bool,
+    /// Counts the lines of tests even with `--ignore-tests`, their coverage is
+    /// also summarised on its own
+    pub count_test_lines: bool,
     /// Ignore panic macros in code.
     pub ignore_panics: bool,
+    /// Count functions whose body is only a panic as coverable
+    pub include_panics: bool,
     /// Flag to skip the clean step when preparing the target project
     pub skip_clean: bool,
+    /// Flag to trace the test executables from an existing build instead of
+    /// compiling the project
+    pub reuse_build: bool,
     /// Verbose flag for printing information to the user
     pub verbose: bool,
+    /// Hide the progress indicators and, unless `--out Stdout` is given, the
+    /// coverage summary
+    pub quiet: bool,
+    /// Print the reasoning behind how every traced line was classified
+    pub explain: bool,
+    /// Check the entry breakpoints of executed functions trapped
+    pub audit: bool,
+    /// Rerun coverage whenever a source file changes
+    pub watch: bool,
+    /// Print how long each phase of the coverage run took
+    pub timings: bool,
     /// Flag to count hits in coverage
     pub count: bool,
     /// Flag specifying to run line coverage (default)
     pub line_coverage: bool,
     /// Flag specifying to run branch coverage
     pub branch_coverage: bool,
+    /// Collect coverage per line or for every instruction address of a line
+    pub granularity: Granularity,
     /// Output files to generate
     pub generate: Vec<OutputFile>,
+    /// Compress the generated report files
+    pub compress: Option<Compression>,
+    /// Path to save a JSON report to for later comparison
+    pub save_report: Option<PathBuf>,
+    /// Path of a previously saved report to print the coverage change against
+    pub compare: Option<PathBuf>,
+    /// Report unit and integration test coverage separately
+    pub breakdown: Option<Breakdown>,
+    /// Format of the summary printed after the coverage results
+    pub summary_format: SummaryFormat,
+    /// Group the coverage summary by file or by module path
+    pub group_by: GroupBy,
+    /// Only run the test with this exact name and report what it covered
+    pub test_name: Option<String>,
+    /// Whether the tests of each executable are ran together, each in its own
+    /// process or through cargo nextest
+    pub runner: Runner,
+    /// Attribute lines expanded from macros to the macro definition or the
+    /// invocation site
+    pub macro_coverage: MacroCoverage,
+    /// Build with `--cfg tarpaulin` so code can be changed for coverage runs
+    pub cfg_tarpaulin: bool,
     /// Key relating to coveralls service or repo
     pub coveralls: Option<String>,
     /// Enum representing CI tool used.
@@ -45,27 +90,121 @@ pub struct Config {
     /// as well as report_uri, then the report will be sent to this endpoint
     /// instead.
     pub report_uri: Option<String>,
+    /// Name of the component the coverage is of, so the reports of several
+    /// runs can be kept apart in one project
+    pub flag: Option<String>,
+    /// Prefix of the report file names
+    pub report_name: Option<String>,
+    /// Follow binaries in the project exec'd by the tests and include their
+    /// coverage
+    pub follow_exec: bool,
     /// Forward unexpected signals back to the tracee. Used for tests which
     /// rely on signals to work.
     pub forward_signals: bool,
+    /// Require Cargo.lock is up to date
+    pub locked: bool,
+    /// Require Cargo.lock and cache are up to date
+    pub frozen: bool,
+    /// Run without accessing the network
+    pub offline: bool,
     /// Include all available features in target build
     pub all_features: bool,
     /// Do not include default features in target build
     pub no_default_features: bool,
     /// Features to include in the target project build
     pub features: Vec<String>,
+    /// Sets of features to build and trace the project with in turn, merging
+    /// the coverage of every run
+    pub feature_matrix: Vec<Vec<String>>,
     /// Build all packages in the workspace
     pub all: bool,
+    /// Build and trace all targets: tests, benches, bins and examples
+    pub all_targets: bool,
+    /// Run the named example instead of the tests
+    pub example: Option<String>,
+    /// Also run and trace the doctests, merging their coverage with the unit
+    /// tests
+    pub doc: bool,
+    /// Trace this prebuilt test executable instead of building the project
+    pub binary: Option<PathBuf>,
+    /// Shell command ran after the tests are compiled
+    pub post_build_script: Option<String>,
+    /// Shell command ran before the tests are traced
+    pub pre_test_script: Option<String>,
+    /// Attach to the running test process with this pid and trace it until
+    /// it exits instead of building and launching the tests
+    pub attach: Option<i32>,
+    /// JSON list of locations and addresses to trace instead of those found
+    /// in the debug info
+    pub trace_plan: Option<PathBuf>,
     /// Packages to include when building the target project
     pub packages: Vec<String>,
     /// Packages to exclude from testing
     pub exclude: Vec<String>,
+    /// Only cover the sources of the package being tested, excluding path
+    /// dependencies and other crates nested inside it
+    pub exclude_dependencies: bool,
+    /// Include code generated by build scripts into their OUT_DIR
+    pub include_out_dir: bool,
+    /// Coveralls report written by a previous run to upload instead of
+    /// running the tests
+    pub upload_coveralls: Option<PathBuf>,
+    /// Directory the paths sent to coveralls are relative to, defaults to the
+    /// root of the git repository
+    pub source_root: Option<PathBuf>,
+    /// How the report paths are written, when not given each report uses its
+    /// own default
+    pub path_style: Option<PathStyle>,
+    /// Only report files changed since the base revision
+    pub changed_only: bool,
+    /// Git revision changes are found relative to, defaults to HEAD
+    pub base: Option<String>,
     /// Files to exclude from testing
     excluded_files: Vec<Regex>,
+    /// Substrings of demangled function names marking them as tests
+    pub test_namespaces: Vec<String>,
+    /// Substring of the demangled name of the main generated by the test
+    /// harness
+    pub generated_main: Option<String>,
+    /// Patterns matching source lines to exclude from coverage
+    excluded_lines: Vec<Regex>,
+    /// Minimum coverage percentage of the whole project
+    pub fail_under: Option<f64>,
+    /// JSON report the overall coverage mustn't drop below
+    pub ratchet: Option<PathBuf>,
+    /// Rewrite the ratchet baseline when coverage increases or create it if
+    /// it's missing
+    pub update_baseline: bool,
+    /// JSON report whose covered lines must all still be covered
+    pub no_regressions: Option<PathBuf>,
+    /// Minimum coverage percentage every file must reach
+    pub fail_under_file: Option<f64>,
+    /// Write the tracer data of each executable before and after
+    /// deduplication to this file
+    pub dump_traces: Option<PathBuf>,
+    /// Include the source of each uncovered region in the JSON report
+    pub embed_source: bool,
+    /// Decimal places of the percentages printed
+    precision: Option<usize>,
+    /// Only print the overall coverage, skipping all other reports
+    pub minimal_report: bool,
+    /// Address to stream line hit events to while the tests run
+    pub emit_socket: Option<String>,
+    /// Files exempt from the per file coverage threshold
+    file_threshold_excluded: Vec<Regex>,
     /// Varargs to be forwarded to the test executables.
     pub varargs: Vec<String>,
     /// Duration to wait before a timeout occurs
     pub test_timeout: Duration,
+    /// How long to keep tracing processes forked by the test after it exits
+    pub post_test_delay: Option<Duration>,
+    /// Number of threads the test executables use, passed on via
+    /// `RUST_TEST_THREADS`
+    pub test_threads: Option<usize>,
+    /// Number of parallel jobs to compile with, defaults to cargo's
+    pub jobs: Option<u32>,
+    /// Target triple to build the tests for, defaults to the host
+    pub target: Option<String>,
 }
 
 impl<'a> From<&'a ArgMatches<'a>> for Config {
@@ -74,31 +213,115 @@ impl<'a> From<&'a ArgMatches<'a>> for Config {
         Config {
             manifest:           get_manifest(args),
             run_ignored:        args.is_present("ignored"),
-            ignore_tests:       args.is_present("ignore-tests"),
+            ignore_tests:       args.is_present("ignore-tests") && !args.is_present("count-test-lines"),
+            count_test_lines:   args.is_present("count-test-lines"),
             ignore_panics:      args.is_present("ignore-panics"),
+            include_panics:     args.is_present("include-panics"),
             skip_clean:         args.is_present("skip-clean"),
+            reuse_build:        args.is_present("reuse-build"),
             verbose:            args.is_present("verbose"),
+            quiet:              args.is_present("quiet"),
+            explain:            args.is_present("explain"),
+            audit:              args.is_present("audit"),
+            watch:              args.is_present("watch"),
+            timings:            args.is_present("timings"),
             count:              args.is_present("count"),
             line_coverage:      get_line_cov(args),
             branch_coverage:    get_branch_cov(args),
+            granularity:        get_granularity(args),
             generate:           get_outputs(args),
+            compress:           get_compression(args),
+            save_report:        args.value_of("save-report").map(PathBuf::from),
+            compare:            args.value_of("compare").map(PathBuf::from),
+            breakdown:          get_breakdown(args),
+            macro_coverage:     get_macro_coverage(args),
+            cfg_tarpaulin:      args.is_present("cfg-tarpaulin"),
+            summary_format:     get_summary_format(args),
+            group_by:           get_group_by(args),
+            runner:             get_runner(args),
+            test_name:          args.value_of("test-name").map(ToString::to_string),
             coveralls:          get_coveralls(args),
             ci_tool:            get_ci(args),
             report_uri:         get_report_uri(args),
+            flag:               args.value_of("flag").map(ToString::to_string),
+            report_name:        args.value_of("report-name").map(ToString::to_string),
+            follow_exec:        args.is_present("follow-exec"),
             forward_signals:    args.is_present("forward"),
+            locked:             args.is_present("locked"),
+            frozen:             args.is_present("frozen"),
+            offline:            args.is_present("offline"),
             all_features:       args.is_present("all-features"),
             no_default_features: args.is_present("no-default-features"),
             features:           get_list(args, "features"),
+            feature_matrix:     get_feature_matrix(args),
             all:                args.is_present("all"),
+            all_targets:        args.is_present("all-targets"),
+            example:            args.value_of("example").map(ToString::to_string),
+            doc:                args.is_present("doc"),
+            binary:             get_input_path(args, "binary"),
+            post_build_script:  args.value_of("post-build-script").map(ToString::to_string),
+            pre_test_script:    args.value_of("pre-test-script").map(ToString::to_string),
+            attach:             get_attach(args),
+            trace_plan:         get_input_path(args, "trace-plan"),
             packages:           get_list(args, "packages"),
             exclude:            get_list(args, "exclude"),
+            exclude_dependencies: args.is_present("exclude-dependencies"),
+            include_out_dir:    args.is_present("include-out-dir"),
+            upload_coveralls:   get_input_path(args, "upload-coveralls"),
+            source_root:        get_input_path(args, "source-root"),
+            path_style:         get_path_style(args),
+            changed_only:       args.is_present("changed-only"),
+            base:               args.value_of("base").map(ToString::to_string),
             excluded_files:     get_excluded(args),
+            test_namespaces:    get_list(args, "test-namespace"),
+            generated_main:     args.value_of("generated-main-symbol").map(ToString::to_string),
+            excluded_lines:     get_line_patterns(args),
+            fail_under:         get_fail_under(args),
+            ratchet:            get_input_path(args, "ratchet"),
+            update_baseline:    args.is_present("update-baseline"),
+            no_regressions:     get_input_path(args, "no-regressions"),
+            fail_under_file:    get_fail_under_file(args),
+            dump_traces:        get_input_path(args, "dump-traces"),
+            embed_source:       args.is_present("embed-source"),
+            precision:          value_t!(args.value_of("precision"), usize).ok(),
+            minimal_report:     args.is_present("minimal-report"),
+            emit_socket:        args.value_of("emit-socket").map(ToString::to_string),
+            file_threshold_excluded: get_threshold_excluded(args),
             varargs:            get_list(args, "args"),
             test_timeout:       get_timeout(args),
+            post_test_delay:    get_post_test_delay(args),
+            test_threads:       get_test_threads(args),
+            jobs:               get_jobs(args),
+            target:             args.value_of("target").map(ToString::to_string),
         }
     }
 }
 
+/// A file pattern matches a path relative to the project root if it matches
+/// anywhere in the path or the file name. So `main.rs` matches `src/main.rs`
+/// and `src/domain.rs`, while `*/lib.rs` only matches a `lib.rs` in a folder.
+fn matches_file(pattern: &Regex, path: &Path) -> bool {
+    pattern.is_match(path.to_str().unwrap_or("")) ||
+        path.file_name()
+            .and_then(|n| n.to_str())
+            .map_or(false, |n| pattern.is_match(n))
+}
+
+/// Mangles a path such as `__test::main` the way it appears in a symbol. Both
+/// the legacy and v0 manglings write each segment as its length followed by
+/// the identifier, v0 adds a `_` before identifiers starting with `_` or a
+/// digit.
+fn mangled_path(path: &str, v0: bool) -> String {
+    path.split("::")
+        .filter(|s| !s.is_empty())
+        .map(|s| {
+            let separator = v0 && s.starts_with(|c: char| c == '_' || c.is_ascii_digit());
+            format!("{}{}{}", s.len(), if separator { "_" } else { "" }, s)
+        })
+        .collect()
+}
+
+
 impl Config {
 
     #[inline]
@@ -108,10 +331,57 @@ impl Config {
 
     #[inline]
     pub fn exclude_path(&self, path: &Path) -> bool {
+        self.excluding_pattern(path).is_some()
+    }
+
+    /// Gets the `--exclude-files` pattern excluding the path as it was given
+    /// on the command line
+    pub fn excluding_pattern(&self, path: &Path) -> Option<String> {
         let project = self.strip_project_path(path);
 
         self.excluded_files.iter()
-            .any(|x| x.is_match(project.to_str().unwrap_or("")))
+            .find(|x| matches_file(x, &project))
+            .map(|x| x.as_str().replace(".*", "*").replace(r"\.", "."))
+    }
+
+    /// Decimal places of the percentages printed, one unless `--precision` is
+    /// given
+    #[inline]
+    pub fn precision(&self) -> usize {
+        self.precision.unwrap_or(1)
+    }
+
+    /// Checks if the demangled function name is in a test namespace, by default
+    /// a `tests` module
+    pub fn is_test_function(&self, name: &str) -> bool {
+        if self.test_namespaces.is_empty() {
+            name.contains("tests::")
+        } else {
+            self.test_namespaces.iter().any(|n| name.contains(n.as_str()))
+        }
+    }
+
+    /// Checks if the demangled function name is the main generated by the
+    /// test harness. Names the demangler doesn't understand are left mangled
+    /// so the mangled forms of the symbol are checked as well.
+    pub fn is_generated_main(&self, name: &str) -> bool {
+        let symbol = self.generated_main.as_ref().map(String::as_str).unwrap_or("__test::main");
+        name.contains(symbol) ||
+            name.contains(&mangled_path(symbol, false)) ||
+            name.contains(&mangled_path(symbol, true))
+    }
+
+    #[inline]
+    pub fn exclude_line(&self, line: &str) -> bool {
+        self.excluded_lines.iter().any(|x| x.is_match(line))
+    }
+
+    #[inline]
+    pub fn exclude_from_file_threshold(&self, path: &Path) -> bool {
+        let project = self.strip_project_path(path);
+
+        self.file_threshold_excluded.iter()
+            .any(|x| matches_file(x, &project))
     }
 
     /// Strips the directory the project manifest is in from the path.
@@ -123,6 +393,35 @@ impl Config {
             .and_then(|x| path_relative_from(path, x))
             .unwrap_or_else(|| path.to_path_buf())
     }
+
+    /// Gets the path relative to the given source root, falling back to the
+    /// path relative to the project if it's not under the root
+    pub fn strip_source_root(&self, path: &Path, root: Option<&Path>) -> PathBuf {
+        root.and_then(|r| path_relative_from(path, r))
+            .unwrap_or_else(|| self.strip_project_path(path))
+    }
+
+    /// Directory the report paths are relative to for the path style, or
+    /// none for absolute paths. Without `--path-style` the report's default
+    /// style is used.
+    pub fn report_root(&self, default: PathStyle) -> Option<PathBuf> {
+        match self.path_style.unwrap_or(default) {
+            PathStyle::Absolute => None,
+            PathStyle::WorkspaceRelative => self.manifest.parent().map(Path::to_path_buf),
+            PathStyle::GitRelative => self.source_root.clone().or_else(|| {
+                self.manifest.parent().and_then(|dir| repository_root(dir).ok())
+            }),
+        }
+    }
+
+    /// Path of a file as written in the reports given the report root. Files
+    /// outside of the root are relative to the project.
+    pub fn report_path(&self, path: &Path, root: Option<&Path>) -> PathBuf {
+        match root {
+            Some(_) => self.strip_source_root(path, root),
+            None => path.to_path_buf(),
+        }
+    }
 }
 
 
@@ -175,6 +474,7 @@ fn path_relative_from(path: &Path, base: &Path) -> Option<PathBuf> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::{env, fs};
     use clap::App;
 
 
@@ -219,6 +519,62 @@ mod tests {
         assert!(!conf.exclude_path(Path::new("lib.rs")));
     }
 
+
+    #[test]
+    fn exclude_same_file_names() {
+        let matches = App::new("tarpaulin")
+            .args_from_usage("--exclude-files [FILE]... 'Exclude given files from coverage results has * wildcard'")
+            .get_matches_from_safe(vec!["tarpaulin", "--exclude-files", "src/a/mod.rs"])
+            .unwrap();
+        let conf = Config::from(&matches);
+        assert!(conf.exclude_path(Path::new("src/a/mod.rs")));
+        assert!(!conf.exclude_path(Path::new("src/b/mod.rs")));
+        assert!(!conf.exclude_path(Path::new("tests/a/mod.rs")));
+
+        let matches = App::new("tarpaulin")
+            .args_from_usage("--exclude-files [FILE]... 'Exclude given files from coverage results has * wildcard'")
+            .get_matches_from_safe(vec!["tarpaulin", "--exclude-files", "mod.rs"])
+            .unwrap();
+        let conf = Config::from(&matches);
+        assert!(conf.exclude_path(Path::new("src/a/mod.rs")));
+        assert!(conf.exclude_path(Path::new("src/b/mod.rs")));
+    }
+
+    #[test]
+    fn default_exclusions() {
+        let matches = App::new("tarpaulin")
+            .args_from_usage("--default-excludes 'If no files are excluded exclude build scripts and the target directory'
+                              --exclude-files [FILE]... 'Exclude given files from coverage results has * wildcard'")
+            .get_matches_from_safe(vec!["tarpaulin", "--default-excludes"])
+            .unwrap();
+        let conf = Config::from(&matches);
+        assert!(conf.exclude_path(Path::new("foo/build.rs")));
+        assert!(conf.exclude_path(Path::new("foo/target/debug/build/out/gen.rs")));
+        assert!(!conf.exclude_path(Path::new("src/lib.rs")));
+
+        let matches = App::new("tarpaulin")
+            .args_from_usage("--default-excludes 'If no files are excluded exclude build scripts and the target directory'
+                              --exclude-files [FILE]... 'Exclude given files from coverage results has * wildcard'")
+            .get_matches_from_safe(vec!["tarpaulin", "--default-excludes", "--exclude-files", "*/lib.rs"])
+            .unwrap();
+        let conf = Config::from(&matches);
+        assert!(!conf.exclude_path(Path::new("foo/build.rs")));
+        assert!(conf.exclude_path(Path::new("src/lib.rs")));
+    }
+
+    #[test]
+    fn file_threshold_exclusions() {
+        let matches = App::new("tarpaulin")
+            .args_from_usage("--fail-under-file [PERCENT] 'Fails if the coverage of any file is below the given percentage'
+                              --file-threshold-exclude [FILE]... 'Files exempt from --fail-under-file has * wildcard'")
+            .get_matches_from_safe(vec!["tarpaulin", "--fail-under-file", "70", "--file-threshold-exclude", "*/generated.rs"])
+            .unwrap();
+        let conf = Config::from(&matches);
+        assert_eq!(conf.fail_under_file, Some(70.0));
+        assert!(conf.exclude_from_file_threshold(Path::new("src/generated.rs")));
+        assert!(!conf.exclude_from_file_threshold(Path::new("src/lib.rs")));
+    }
+
     #[test]
     fn relative_path_test() {
         let path_a = Path::new("/this/should/form/a/rel/path/");
@@ -242,5 +598,210 @@ mod tests {
         assert!(rel_path.is_some());
         assert_eq!(rel_path.unwrap().to_str().unwrap(), "../../../b/rel/path", "Wrong relative path");
     }
-}
 
+
+
+    #[test]
+    fn feature_matrix() {
+        let matches = App::new("tarpaulin")
+            .args_from_usage("--feature-matrix [FEATURES]... 'Sets of features to build and run the tests with in turn, merging the coverage'")
+            .get_matches_from_safe(vec!["tarpaulin", "--feature-matrix", "default", "--feature-matrix", "full net tls"])
+            .unwrap();
+        let conf = Config::from(&matches);
+        assert_eq!(conf.feature_matrix.len(), 2);
+        assert!(conf.feature_matrix[0].is_empty());
+        assert_eq!(conf.feature_matrix[1], vec!["full", "net", "tls"]);
+    }
+
+
+
+    #[test]
+    fn exclude_line_patterns() {
+        let matches = App::new("tarpaulin")
+            .args_from_usage("--exclude-line-pattern [REGEX]... 'Exclude source lines matching the regex from coverage results'")
+            .get_matches_from_safe(vec!["tarpaulin", "--exclude-line-pattern", r"unreachable!\(\)", "log::trace!"])
+            .unwrap();
+        let conf = Config::from(&matches);
+        assert!(conf.exclude_line("        _ => unreachable!(),"));
+        assert!(conf.exclude_line("    log::trace!(\"value {}\", x);"));
+        assert!(!conf.exclude_line("    unreachable_code();"));
+    }
+
+
+
+    #[test]
+    fn test_function_detection() {
+        let conf = Config::default();
+        assert!(conf.is_test_function("my_crate::tests::it_works"));
+        assert!(!conf.is_test_function("my_crate::spec::it_works"));
+        assert!(conf.is_generated_main("my_crate::__test::main"));
+        assert!(conf.is_generated_main("_ZN8my_crate6__test4main17h0123456789abcdefE"));
+        assert!(conf.is_generated_main("_RNvNtCs1234_8my_crate6___test4main"));
+        assert!(!conf.is_generated_main("_RNvNtCs1234_8my_crate5tests4main"));
+
+        let matches = App::new("tarpaulin")
+            .args_from_usage("--test-namespace [SUBSTR]... 'Functions with demangled names containing this are tests (default tests::)'
+                              --generated-main-symbol [SUBSTR] 'Demangled name of the main generated by the test harness (default __test::main)'")
+            .get_matches_from_safe(vec!["tarpaulin", "--test-namespace", "spec::", "--generated-main-symbol", "runner::main"])
+            .unwrap();
+        let conf = Config::from(&matches);
+        assert!(conf.is_test_function("my_crate::spec::it_works"));
+        assert!(!conf.is_test_function("my_crate::tests::it_works"));
+        assert!(conf.is_generated_main("my_crate::runner::main"));
+        assert!(!conf.is_generated_main("my_crate::__test::main"));
+    }
+
+
+    #[test]
+    fn source_root_paths() {
+        let mut conf = Config::default();
+        conf.manifest = PathBuf::from("/repo/crates/foo/Cargo.toml");
+        let file = Path::new("/repo/crates/foo/src/lib.rs");
+        assert_eq!(conf.strip_source_root(file, Some(Path::new("/repo"))),
+                   PathBuf::from("crates/foo/src/lib.rs"));
+        assert_eq!(conf.strip_source_root(file, None), PathBuf::from("src/lib.rs"));
+    }
+
+
+    #[test]
+    fn path_styles() {
+        let mut conf = Config::default();
+        conf.manifest = PathBuf::from("/repo/crates/foo/Cargo.toml");
+        conf.source_root = Some(PathBuf::from("/repo"));
+        let file = Path::new("/repo/crates/foo/src/lib.rs");
+        let path = |style| {
+            let root = conf.report_root(style);
+            conf.report_path(file, root.as_ref().map(|r| r.as_path()))
+        };
+        assert_eq!(path(PathStyle::Absolute), file);
+        assert_eq!(path(PathStyle::WorkspaceRelative), PathBuf::from("src/lib.rs"));
+        assert_eq!(path(PathStyle::GitRelative), PathBuf::from("crates/foo/src/lib.rs"));
+
+        let matches = App::new("tarpaulin")
+            .args_from_usage("--path-style [STYLE] 'How report paths are written'")
+            .get_matches_from_safe(vec!["tarpaulin", "--path-style", "git-relative"])
+            .unwrap();
+        let conf = Config::from(&matches);
+        assert_eq!(conf.path_style, Some(PathStyle::GitRelative));
+        assert!("workspace".parse::<PathStyle>().is_err());
+    }
+
+
+    #[test]
+    fn overall_threshold() {
+        let matches = App::new("tarpaulin")
+            .args_from_usage("--fail-under [PERCENT] 'Fails if the overall coverage is below the given percentage'
+                              --minimal-report 'Only prints the overall coverage, skipping the per file results and report files'")
+            .get_matches_from_safe(vec!["tarpaulin", "--fail-under", "80", "--minimal-report"])
+            .unwrap();
+        let conf = Config::from(&matches);
+        assert_eq!(conf.fail_under, Some(80.0));
+        assert!(conf.minimal_report);
+    }
+
+
+    #[test]
+    fn exclusion_patterns() {
+        let matches = App::new("tarpaulin")
+            .args_from_usage("--exclude-files [FILE]... 'Exclude given files from coverage results has * wildcard'")
+            .get_matches_from_safe(vec!["tarpaulin", "--exclude-files", "src/gen*", "build.rs"])
+            .unwrap();
+        let conf = Config::from(&matches);
+        assert_eq!(conf.excluding_pattern(Path::new("src/generated.rs")), Some("src/gen*".to_string()));
+        assert_eq!(conf.excluding_pattern(Path::new("build.rs")), Some("build.rs".to_string()));
+        assert_eq!(conf.excluding_pattern(Path::new("src/lib.rs")), None);
+    }
+
+
+    #[test]
+    fn ignore_file() {
+        let root = env::temp_dir().join("tarpaulin-ignore-file");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join(".tarpaulinignore"), "# generated code\nsrc/generated/*\n\n").unwrap();
+        let matches = App::new("tarpaulin")
+            .args_from_usage("--root -r [DIR]  'Root directory containing Cargo.toml to use'
+                              --exclude-files [FILE]... 'Exclude given files from coverage results has * wildcard'")
+            .get_matches_from_safe(vec!["tarpaulin", "--root", root.to_str().unwrap(),
+                                        "--exclude-files", "build.rs"])
+            .unwrap();
+        let conf = Config::from(&matches);
+        assert!(conf.exclude_path(&root.join("src/generated/parser.rs")));
+        assert!(conf.exclude_path(&root.join("build.rs")));
+        assert!(!conf.exclude_path(&root.join("src/lib.rs")));
+        let _ = fs::remove_dir_all(&root);
+    }
+
+
+    #[test]
+    fn exclude_file_names() {
+        let matches = App::new("tarpaulin")
+            .args_from_usage("--exclude-files [FILE]... 'Exclude given files from coverage results has * wildcard'")
+            .get_matches_from_safe(vec!["tarpaulin", "--exclude-files", "main.rs", "gen_*.rs"])
+            .unwrap();
+        let conf = Config::from(&matches);
+        assert!(conf.exclude_path(Path::new("src/main.rs")));
+        assert!(conf.exclude_path(Path::new("main.rs")));
+        // Patterns match anywhere in the path
+        assert!(conf.exclude_path(Path::new("src/domain.rs")));
+        assert!(conf.exclude_path(Path::new("src/main.rs/mod.rs")));
+        assert!(!conf.exclude_path(Path::new("src/lib.rs")));
+        assert!(conf.exclude_path(Path::new("src/proto/gen_types.rs")));
+        assert_eq!(conf.excluding_pattern(Path::new("src/main.rs")), Some("main.rs".to_string()));
+    }
+
+
+    #[test]
+    fn percentage_precision() {
+        assert_eq!(Config::default().precision(), 1);
+        let matches = App::new("tarpaulin")
+            .args_from_usage("--precision [DIGITS] 'Number of decimal places in the printed percentages (default 1)'")
+            .get_matches_from_safe(vec!["tarpaulin", "--precision", "2"])
+            .unwrap();
+        let conf = Config::from(&matches);
+        assert_eq!(conf.precision(), 2);
+        assert_eq!(format!("{:.prec$}%", 79.6234f64, prec = conf.precision()), "79.62%");
+    }
+
+
+    #[test]
+    fn count_test_lines() {
+        let matches = App::new("tarpaulin")
+            .args_from_usage("--ignore-tests 'ignore lines of test functions when collecting coverage'
+                              --count-test-lines 'Count the lines of tests even with --ignore-tests'")
+            .get_matches_from_safe(vec!["tarpaulin", "--ignore-tests"])
+            .unwrap();
+        let conf = Config::from(&matches);
+        assert!(conf.ignore_tests);
+        assert!(!conf.count_test_lines);
+
+        let matches = App::new("tarpaulin")
+            .args_from_usage("--ignore-tests 'ignore lines of test functions when collecting coverage'
+                              --count-test-lines 'Count the lines of tests even with --ignore-tests'")
+            .get_matches_from_safe(vec!["tarpaulin", "--ignore-tests", "--count-test-lines"])
+            .unwrap();
+        let conf = Config::from(&matches);
+        assert!(!conf.ignore_tests);
+        assert!(conf.count_test_lines);
+    }
+
+
+    #[test]
+    fn update_baseline() {
+        let matches = App::new("tarpaulin")
+            .args_from_usage("--ratchet [FILE] 'Fails if coverage is below that of the JSON report'
+                              --update-baseline 'Updates the --ratchet report when coverage increases or creates it if missing'")
+            .get_matches_from_safe(vec!["tarpaulin", "--ratchet", "baseline.json"])
+            .unwrap();
+        let conf = Config::from(&matches);
+        assert!(conf.ratchet.is_some());
+        assert!(!conf.update_baseline);
+
+        let matches = App::new("tarpaulin")
+            .args_from_usage("--ratchet [FILE] 'Fails if coverage is below that of the JSON report'
+                              --update-baseline 'Updates the --ratchet report when coverage increases or creates it if missing'")
+            .get_matches_from_safe(vec!["tarpaulin", "--ratchet", "baseline.json", "--update-baseline"])
+            .unwrap();
+        let conf = Config::from(&matches);
+        assert!(conf.update_baseline);
+    }
+}
@@ -1,4 +1,5 @@
 use std::env;
+use std::fs;
 use std::path::{PathBuf};
 use std::time::{Duration};
 
@@ -14,6 +15,20 @@ pub(super) fn get_list(args: &ArgMatches, key: &str) -> Vec<String> {
 }
 
 
+/// Each feature set is a space or comma separated list of features. The name
+/// `default` on its own selects only the default features.
+pub(super) fn get_feature_matrix(args: &ArgMatches) -> Vec<Vec<String>> {
+    get_list(args, "feature-matrix").iter()
+                                    .map(|set| {
+                                        set.split(|c: char| c == ',' || c.is_whitespace())
+                                           .filter(|f| !f.is_empty() && *f != "default")
+                                           .map(ToString::to_string)
+                                           .collect::<Vec<_>>()
+                                    })
+                                    .collect()
+}
+
+
 pub(super) fn get_line_cov(args: &ArgMatches) -> bool {
     let cover_lines = args.is_present("line");
     let cover_branches = args.is_present("branch");
@@ -22,16 +37,17 @@ pub(super) fn get_line_cov(args: &ArgMatches) -> bool {
 }
 
 
+/// Branch coverage changes what lines count so is only collected when asked
+/// for
 pub(super) fn get_branch_cov(args: &ArgMatches) -> bool {
-    let cover_lines = args.is_present("line");
-    let cover_branches = args.is_present("branch");
-
-    cover_branches || !(cover_lines || cover_branches)
+    args.is_present("branch")
 }
 
 
+/// Without a current directory only an absolute root can be used, this is
+/// checked before the config is parsed.
 pub(super) fn get_manifest(args: &ArgMatches) -> PathBuf {
-    let mut manifest = env::current_dir().unwrap();
+    let mut manifest = env::current_dir().unwrap_or_default();
 
     if let Some(path) = args.value_of("root") {
         manifest.push(path);
@@ -42,13 +58,44 @@ pub(super) fn get_manifest(args: &ArgMatches) -> PathBuf {
 }
 
 
+/// Paths to test binaries, trace plans, source roots and saved reports are
+/// relative to the current directory rather than the project root
+pub(super) fn get_input_path(args: &ArgMatches, name: &str) -> Option<PathBuf> {
+    args.value_of(name).map(|b| {
+        let path = env::current_dir().map(|d| d.join(b)).unwrap_or_else(|_| PathBuf::from(b));
+        path.canonicalize().unwrap_or(path)
+    })
+}
+
+
+pub(super) fn get_attach(args: &ArgMatches) -> Option<i32> {
+    value_t!(args.value_of("attach"), i32).ok()
+}
+
+
 pub(super) fn get_ci(args: &ArgMatches) -> Option<CiService> {
     value_t!(args, "ciserver", Ci).map(|x| x.0).ok()
 }
 
 
+/// Gets the coveralls key from the command line, falling back to a token file
+/// and then the `COVERALLS_REPO_TOKEN` environment variable so the token
+/// doesn't have to appear in the process arguments.
 pub(super) fn get_coveralls(args: &ArgMatches) -> Option<String> {
-    args.value_of("coveralls").map(ToString::to_string)
+    if let Some(key) = args.value_of("coveralls") {
+        Some(key.to_string())
+    } else if let Some(path) = args.value_of("coveralls-token-file") {
+        match fs::read_to_string(path) {
+            Ok(token) => Some(token.trim().to_string()),
+            Err(e) => {
+                eprintln!("Failed to read coveralls token file {}: {}", path, e);
+                None
+            },
+        }
+    } else {
+        env::var("COVERALLS_REPO_TOKEN").ok()
+                                        .filter(|x| !x.is_empty())
+    }
 }
 
 
@@ -62,10 +109,115 @@ pub(super) fn get_outputs(args: &ArgMatches) -> Vec<OutputFile> {
 }
 
 
+pub(super) fn get_compression(args: &ArgMatches) -> Option<Compression> {
+    value_t!(args, "compress", Compression).ok()
+}
+
+
+pub(super) fn get_breakdown(args: &ArgMatches) -> Option<Breakdown> {
+    value_t!(args, "breakdown", Breakdown).ok()
+}
+
+
+pub(super) fn get_macro_coverage(args: &ArgMatches) -> MacroCoverage {
+    value_t!(args, "macro-coverage", MacroCoverage).unwrap_or_default()
+}
+
+
+pub(super) fn get_granularity(args: &ArgMatches) -> Granularity {
+    value_t!(args, "granularity", Granularity).unwrap_or_default()
+}
+
+
+pub(super) fn get_path_style(args: &ArgMatches) -> Option<PathStyle> {
+    value_t!(args, "path-style", PathStyle).ok()
+}
+
+
+pub(super) fn get_runner(args: &ArgMatches) -> Runner {
+    value_t!(args, "runner", Runner).unwrap_or_default()
+}
+
+
+pub(super) fn get_group_by(args: &ArgMatches) -> GroupBy {
+    value_t!(args, "group-by", GroupBy).unwrap_or_default()
+}
+
+
+pub(super) fn get_summary_format(args: &ArgMatches) -> SummaryFormat {
+    value_t!(args, "print-summary", SummaryFormat).unwrap_or_default()
+}
+
+
+/// Files excluded by `--default-excludes` when no files are explicitly
+/// excluded. Build scripts and anything in the target directory are generated
+/// or not part of the tested code.
+const DEFAULT_EXCLUDES: &[&str] = &["*/build.rs", "*/target/*"];
+
+
+/// File in the project root listing patterns of files to exclude, one per
+/// line, which are added to those given with `--exclude-files`
+const IGNORE_FILE: &str = ".tarpaulinignore";
+
+
+/// Reads the patterns in the ignore file if the project has one, blank lines
+/// and lines starting with `#` are skipped.
+fn get_ignore_file(args: &ArgMatches) -> Vec<String> {
+    let manifest = get_manifest(args);
+    let path = match manifest.parent() {
+        Some(root) => root.join(IGNORE_FILE),
+        None => return vec![],
+    };
+    match fs::read_to_string(&path) {
+        Ok(contents) => contents.lines()
+                                .map(str::trim)
+                                .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                                .map(ToString::to_string)
+                                .collect(),
+        Err(_) => vec![],
+    }
+}
+
+
 pub(super) fn get_excluded(args: &ArgMatches) -> Vec<Regex> {
+    let mut excluded = get_list(args, "exclude-files");
+    excluded.extend(get_ignore_file(args));
+    if excluded.is_empty() && args.is_present("default-excludes") {
+        let defaults = DEFAULT_EXCLUDES.iter()
+                                       .map(ToString::to_string)
+                                       .collect::<Vec<_>>();
+        get_file_patterns(&defaults)
+    } else {
+        get_file_patterns(&excluded)
+    }
+}
+
+
+pub(super) fn get_threshold_excluded(args: &ArgMatches) -> Vec<Regex> {
+    get_file_patterns(&get_list(args, "file-threshold-exclude"))
+}
+
+
+/// Line patterns are regular expressions matched against the source code of
+/// each line, unlike file patterns `*` isn't treated as a wildcard.
+pub(super) fn get_line_patterns(args: &ArgMatches) -> Vec<Regex> {
+    get_list(args, "exclude-line-pattern").iter()
+                                          .filter_map(|p| match Regex::new(p) {
+                                              Ok(re) => Some(re),
+                                              Err(_) => {
+                                                  eprintln!("Invalid regex: {}", p);
+                                                  None
+                                              },
+                                          })
+                                          .collect()
+}
+
+
+/// File patterns can match anywhere in the path, `*` is a wildcard
+fn get_file_patterns(patterns: &[String]) -> Vec<Regex> {
     let mut files = vec![];
 
-    for temp_str in &get_list(args, "exclude-files") {
+    for temp_str in patterns {
         let s = &temp_str.replace(".", r"\.").replace("*", ".*");
 
         if let Ok(re) = Regex::new(s) {
@@ -90,3 +242,27 @@ pub(super) fn get_timeout(args: &ArgMatches) -> Duration {
     }
 }
 
+
+pub(super) fn get_post_test_delay(args: &ArgMatches) -> Option<Duration> {
+    value_t!(args.value_of("post-test-delay"), u64).ok().map(Duration::from_millis)
+}
+
+
+pub(super) fn get_test_threads(args: &ArgMatches) -> Option<usize> {
+    value_t!(args.value_of("test-threads"), usize).ok()
+}
+
+
+pub(super) fn get_jobs(args: &ArgMatches) -> Option<u32> {
+    value_t!(args.value_of("jobs"), u32).ok()
+}
+
+
+pub(super) fn get_fail_under(args: &ArgMatches) -> Option<f64> {
+    value_t!(args.value_of("fail-under"), f64).ok()
+}
+
+
+pub(super) fn get_fail_under_file(args: &ArgMatches) -> Option<f64> {
+    value_t!(args.value_of("fail-under-file"), f64).ok()
+}
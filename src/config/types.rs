@@ -9,9 +9,139 @@ arg_enum! {
     #[derive(Debug)]
     pub enum OutputFile {
         Json,
+        JsonLines,
         Toml,
         Stdout,
         Xml,
+        Sqlite,
+        Coveralls,
+        Codecov,
+        Html,
+    }
+}
+
+arg_enum! {
+
+    /// Selects which kinds of test coverage are reported separately
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Breakdown {
+        Unit,
+        Integration,
+        Both,
+    }
+}
+
+arg_enum! {
+
+    /// Where lines expanded from `macro_rules!` macros are attributed
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum MacroCoverage {
+        Def,
+        Invocation,
+    }
+}
+
+impl Default for MacroCoverage {
+
+    #[inline]
+    fn default() -> Self {
+        MacroCoverage::Invocation
+    }
+}
+
+arg_enum! {
+
+    /// Style of the coverage summary printed at the end of a run
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum SummaryFormat {
+        Human,
+        Machine,
+    }
+}
+
+arg_enum! {
+
+    /// How the coverage of each group is listed in the summary
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum GroupBy {
+        File,
+        Module,
+    }
+}
+
+impl Default for GroupBy {
+
+    #[inline]
+    fn default() -> Self {
+        GroupBy::File
+    }
+}
+
+arg_enum! {
+
+    /// Whether coverage is collected per line, per instruction address or
+    /// per function
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Granularity {
+        Line,
+        Instruction,
+        Function,
+    }
+}
+
+impl Default for Granularity {
+
+    #[inline]
+    fn default() -> Self {
+        Granularity::Line
+    }
+}
+
+impl Granularity {
+
+    /// What the covered and coverable counts are of
+    pub fn counted(self) -> &'static str {
+        match self {
+            Granularity::Function => "functions",
+            _ => "lines",
+        }
+    }
+}
+
+arg_enum! {
+
+    /// How the tests in each test executable are ran, isolated runs every test
+    /// in its own process and nextest runs them through `cargo nextest`
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Runner {
+        Libtest,
+        Isolated,
+        Nextest,
+    }
+}
+
+impl Default for Runner {
+
+    #[inline]
+    fn default() -> Self {
+        Runner::Libtest
+    }
+}
+
+arg_enum! {
+
+    /// Compression applied to the generated report files
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Compression {
+        Gzip,
+    }
+}
+
+impl Default for SummaryFormat {
+
+    #[inline]
+    fn default() -> Self {
+        SummaryFormat::Human
     }
 }
 
@@ -24,6 +154,39 @@ impl Default for OutputFile {
 }
 
 
+/// How the paths of source files are written in the reports
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathStyle {
+    /// Absolute paths
+    Absolute,
+    /// Relative to the directory of the project manifest
+    WorkspaceRelative,
+    /// Relative to the root of the git repository, or the source root if given
+    GitRelative,
+}
+
+impl PathStyle {
+
+    /// Names the path styles are given by on the command line
+    pub fn variants() -> [&'static str; 3] {
+        ["absolute", "workspace-relative", "git-relative"]
+    }
+}
+
+impl FromStr for PathStyle {
+    type Err = String;
+
+    fn from_str(x: &str) -> Result<PathStyle, Self::Err> {
+        match x.to_lowercase().as_str() {
+            "absolute"              => Ok(PathStyle::Absolute),
+            "workspace-relative"    => Ok(PathStyle::WorkspaceRelative),
+            "git-relative"          => Ok(PathStyle::GitRelative),
+            other                   => Err(format!("unknown path style {}", other)),
+        }
+    }
+}
+
+
 pub struct Ci(pub CiService);
 
 impl FromStr for Ci {
@@ -0,0 +1,39 @@
+use std::io::{self, Write};
+use std::net::{TcpStream, UdpSocket};
+use std::path::Path;
+
+
+/// Socket line hit events are streamed to as breakpoints trap, for tools
+/// showing coverage live while the tests run
+pub enum HitEmitter {
+    Tcp(TcpStream),
+    Udp(UdpSocket),
+}
+
+impl HitEmitter {
+    /// Connects to the address. Addresses starting with `udp://` send each
+    /// event as a datagram, otherwise a TCP connection is used.
+    pub fn connect(addr: &str) -> io::Result<HitEmitter> {
+        if addr.starts_with("udp://") {
+            let socket = UdpSocket::bind("0.0.0.0:0")?;
+            socket.connect(&addr["udp://".len()..])?;
+            Ok(HitEmitter::Udp(socket))
+        } else {
+            let addr = addr.trim_start_matches("tcp://");
+            TcpStream::connect(addr).map(HitEmitter::Tcp)
+        }
+    }
+
+    /// Sends a hit as a line of JSON with the path and line number
+    pub fn emit(&mut self, path: &Path, line: u64) -> io::Result<()> {
+        let mut event = json!({
+            "path": path,
+            "line": line,
+        }).to_string();
+        event.push('\n');
+        match *self {
+            HitEmitter::Tcp(ref mut stream) => stream.write_all(event.as_bytes()),
+            HitEmitter::Udp(ref socket) => socket.send(event.as_bytes()).map(|_| ()),
+        }
+    }
+}
@@ -4,27 +4,44 @@ extern crate gimli;
 extern crate object;
 extern crate memmap;
 extern crate coveralls_api;
+extern crate curl;
 extern crate fallible_iterator;
+extern crate flate2;
+extern crate indicatif;
 extern crate rustc_demangle;
 extern crate syn;
+extern crate toml;
 extern crate proc_macro2;
 #[macro_use]
 extern crate clap;
 #[macro_use]
 extern crate lazy_static;
+#[macro_use]
+extern crate log;
 extern crate serde;
+#[macro_use]
 extern crate serde_json;
 extern crate quick_xml;
 extern crate regex;
+extern crate rusqlite;
 extern crate void;
 extern crate walkdir;
 
-use std::{env, io};
+use std::env;
+use std::fs;
+use std::io;
+use std::process::{Command, Stdio};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::ffi::CString;
+use std::time::{Instant, SystemTime};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
 use nix::unistd::*;
+use nix::libc::c_int;
+use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
 use cargo::util::{homedir, Config as CargoConfig};
-use cargo::core::{Workspace, Package, compiler::CompileMode, Shell};
+use cargo::core::{Workspace, Package, TargetKind, compiler::CompileMode, Shell};
 use cargo::ops;
 
 
@@ -35,18 +52,67 @@ pub mod report;
 pub mod traces;
 mod statemachine;
 mod source_analysis;
+mod timings;
+mod changes;
+mod emit;
+mod progress;
+mod watch;
 
 /// Should be unnecessary with a future nix crate release.
 mod personality;
 mod ptrace_control;
 
 use config::*;
+use report::DynReport;
 use test_loader::*;
+use progress::Progress;
 use ptrace_control::*;
+use source_analysis::get_line_analysis;
 use statemachine::*;
 use traces::*;
 
 
+/// Exit code of the forked child if it's unable to exec the test binary. Exit
+/// codes are only read this way if the child exited before the exec trap, the
+/// test itself exiting with the same code is a normal failure.
+const EXEC_FAILED: i32 = 127;
+
+/// Exit code of the forked child if it isn't permitted to be traced, like
+/// `EXEC_FAILED` only read if the child exited before the exec trap
+const TRACE_FAILED: i32 = 126;
+
+/// Percentage points coverage can differ from the ratchet baseline by and be
+/// considered unchanged
+const RATCHET_TOLERANCE: f64 = 1e-6;
+
+
+/// Set when tarpaulin is interrupted so the run stops early and reports the
+/// coverage collected so far
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+
+extern "C" fn handle_interrupt(_: c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+/// Installs a SIGINT handler so interrupting tarpaulin stops tracing and
+/// still reports the coverage of the tests which have run.
+pub fn install_interrupt_handler() -> nix::Result<()> {
+    let action = SigAction::new(SigHandler::Handler(handle_interrupt),
+                                SaFlags::empty(),
+                                SigSet::empty());
+    unsafe {
+        sigaction(Signal::SIGINT, &action)?;
+    }
+    Ok(())
+}
+
+/// Returns true if tarpaulin has been interrupted
+pub fn is_interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+
 /// Error states that could be returned from tarpaulin
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum RunError {
@@ -60,13 +126,111 @@ pub enum RunError {
     TestCompileFailed,
     /// Test failed during run
     TestRuntimeFail,
-    
+    /// Coverage of one or more files is below the per file threshold
+    FileThreshold,
+    /// Overall coverage is below the threshold
+    CoverageThreshold,
+    /// The coveralls key is malformed
+    CoverallsKey,
+    /// Uploading a saved coveralls report failed
+    CoverallsUpload,
+    /// Overall coverage dropped below the ratchet baseline
+    Ratchet,
+    /// Lines covered in the baseline report are no longer covered
+    Regression,
+    /// The process given to `--attach` couldn't be traced
+    Attach,
+    /// A post build or pre test script failed
+    Hook,
+    /// An option needing a nightly toolchain was given on another toolchain
+    Nightly,
 }
 
 
+/// Name and root directory of a workspace member
+pub type PackageRoot = (String, PathBuf);
+
+
 pub fn run(config: &Config) -> Result<(), RunError> {
-    let (result, tp) = launch_tarpaulin(config)?;
-    report_coverage(config, &result);
+    run_with_reports(config, &[])
+}
+
+/// Runs tarpaulin like `run`, the given reports are exported with the final
+/// coverage results after the reports chosen in the config. This lets
+/// programs embedding tarpaulin add their own report formats.
+pub fn run_with_reports(config: &Config, reports: &[Box<DynReport>]) -> Result<(), RunError> {
+    if let Err(e) = report::coveralls::validate_key(config) {
+        println!("Invalid coveralls key: {}", e);
+        return Err(RunError::CoverallsKey);
+    }
+    if let Some(ref path) = config.upload_coveralls {
+        // Uploading a saved report doesn't need the tests to be ran
+        println!("Uploading {}", path.display());
+        return report::coveralls::upload_file(path, config).map_err(|e| {
+            println!("Coveralls upload failed: {}", e);
+            RunError::CoverallsUpload
+        });
+    }
+    if config.watch {
+        return watch_project(config, reports);
+    }
+    let (breakdown, members, tp) = launch(config)?;
+    let result = timings::time("merge", || merge_breakdown(config, &breakdown));
+    if config.minimal_report {
+        println!("{:.prec$}% coverage, {}/{} {} covered", result.coverage_percentage() * 100.0f64,
+                 result.total_covered(), result.total_coverable(), config.granularity.counted(),
+                 prec = config.precision());
+    } else {
+        timings::time("export", || report_coverage(config, &result));
+        if let Some(ref baseline) = config.compare {
+            report_delta(config, &result, baseline);
+        }
+        if let Some(ref path) = config.save_report {
+            if let Err(e) = report::json::save(&result, config, path) {
+                println!("Failed to save report to {}: {}", path.display(), e);
+            }
+        }
+        if members.len() > 1 {
+            report_packages(config, &result, &members);
+        }
+        if config.breakdown.is_some() {
+            report_breakdown(config, &breakdown);
+        }
+    }
+    timings::time("custom reports", || export_reports(config, &result, reports));
+    if config.timings {
+        timings::report();
+    }
+    if let Some(ref path) = config.dump_traces {
+        if let Err(e) = write_trace_dump(path) {
+            println!("Failed to dump the traces to {}: {}", path.display(), e);
+        }
+    }
+    if let Some(threshold) = config.fail_under_file {
+        let failing = files_below_threshold(config, &result, threshold);
+        if !failing.is_empty() {
+            println!("Files below {:.prec$}% coverage:", threshold, prec = config.precision());
+            for (path, percent) in failing {
+                println!("{}: {:.prec$}%", config.strip_project_path(&path).display(), percent,
+                         prec = config.precision());
+            }
+            return Err(RunError::FileThreshold);
+        }
+    }
+    if let Some(threshold) = config.fail_under {
+        let percent = result.coverage_percentage() * 100.0f64;
+        if percent < threshold {
+            println!("Coverage of {:.prec$}% is below the threshold of {:.prec$}%", percent, threshold,
+                     prec = config.precision());
+            return Err(RunError::CoverageThreshold);
+        }
+    }
+    if let Some(ref baseline) = config.ratchet {
+        ratchet(config, &result, baseline)?;
+    }
+    if let Some(ref baseline) = config.no_regressions {
+        check_regressions(config, &result, baseline)?;
+    }
     if tp {
         Ok(())
     } else {
@@ -75,8 +239,120 @@ pub fn run(config: &Config) -> Result<(), RunError> {
     }
 }
 
+/// Reruns coverage every time a source file in the project changes, printing
+/// the change in coverage between runs. Runs until tarpaulin is interrupted.
+fn watch_project(config: &Config, reports: &[Box<DynReport>]) -> Result<(), RunError> {
+    let root = config.manifest.parent().ok_or(RunError::Manifest)?;
+    // Until a run finds the workspace members only the root package is watched
+    let mut watched = watch::watched_paths(&[root.to_path_buf()]);
+    let mut last_percent: Option<f64> = None;
+    loop {
+        let started = SystemTime::now();
+        match launch(config) {
+            Ok((breakdown, members, _)) => {
+                let roots = Some(root.to_path_buf()).into_iter()
+                                                    .chain(members.into_iter().map(|(_, r)| r))
+                                                    .collect::<Vec<_>>();
+                watched = watch::watched_paths(&roots);
+                let result = merge_breakdown(config, &breakdown);
+                report_coverage(config, &result);
+                export_reports(config, &result, reports);
+                let percent = result.coverage_percentage() * 100.0f64;
+                if let Some(last) = last_percent {
+                    println!("Coverage changed by {:+.prec$}% since the last run", percent - last,
+                             prec = config.precision());
+                }
+                last_percent = Some(percent);
+            },
+            Err(e) => println!("Coverage run failed: {:?}", e),
+        }
+        if is_interrupted() {
+            return Ok(());
+        }
+        println!("Watching for changes");
+        watch::wait_for_change(&watched, started);
+        if is_interrupted() {
+            return Ok(());
+        }
+    }
+}
+
+/// Exports the reports given to `run_with_reports`
+fn export_reports(config: &Config, result: &TraceMap, reports: &[Box<DynReport>]) {
+    for report in reports {
+        report.export(result, config);
+    }
+}
+
 /// Launches tarpaulin with the given configuration.
 pub fn launch_tarpaulin(config: &Config) -> Result<(TraceMap, bool), RunError> {
+    launch(config).map(|(breakdown, _, tp)| (merge_breakdown(config, &breakdown), tp))
+}
+
+/// Merges the unit and integration test coverage, collapsing the traces of
+/// each line into one unless instruction granularity was requested. With
+/// `--changed-only` only files changed since the base revision are kept.
+fn merge_breakdown(config: &Config, breakdown: &CoverageBreakdown) -> TraceMap {
+    let mut result = match config.granularity {
+        Granularity::Line | Granularity::Function => breakdown.total(),
+        Granularity::Instruction => breakdown.merged(),
+    };
+    if config.changed_only {
+        let root = config.manifest.parent().unwrap_or_else(|| Path::new("."));
+        let base = config.base.as_ref().map(String::as_str).unwrap_or("HEAD");
+        match changes::changed_files(root, base) {
+            Ok(changed) => result.retain_files(|f| changed.contains(f)),
+            Err(e) => println!("Failed to get the files changed since {}: {}", base, e),
+        }
+    }
+    result
+}
+
+/// Launches tarpaulin keeping the unit and integration test results separate.
+/// Also returns the workspace members so coverage can be grouped by package.
+/// With a feature matrix the project is built and traced once per feature set
+/// and the results merged.
+fn launch(config: &Config) -> Result<(CoverageBreakdown, Vec<PackageRoot>, bool), RunError> {
+    if config.macro_coverage == MacroCoverage::Def && !is_nightly() {
+        println!("--macro-coverage def needs a nightly toolchain for -Z debug-macros");
+        return Err(RunError::Nightly);
+    }
+    if config.doc && !is_nightly() {
+        println!("--doc needs a nightly toolchain to keep the compiled doctests");
+        return Err(RunError::Nightly);
+    }
+    // Set once for all the feature sets so the flags aren't added per build
+    setup_environment(config);
+    if config.feature_matrix.is_empty() {
+        return launch_with_features(config, &config.features, true);
+    }
+    let mut result = CoverageBreakdown::new();
+    let mut members = vec![];
+    let mut test_passed = true;
+    for (i, features) in config.feature_matrix.iter().enumerate() {
+        println!("Running with features [{}]", features.join(", "));
+        // Changing features causes a rebuild so only clean the first time
+        let (coverage, m, tp) = launch_with_features(config, features, i == 0)?;
+        result.unit.merge(&coverage.unit);
+        result.integration.merge(&coverage.integration);
+        members = m;
+        test_passed &= tp;
+        if is_interrupted() {
+            break;
+        }
+    }
+    if config.granularity != Granularity::Instruction {
+        timings::time("merge", || {
+            result.unit.dedup();
+            result.integration.dedup();
+        });
+    }
+    Ok((result, members, test_passed))
+}
+
+fn launch_with_features(config: &Config,
+                        features: &[String],
+                        clean: bool) -> Result<(CoverageBreakdown, Vec<PackageRoot>, bool), RunError> {
     let cwd = match config.manifest.parent() {
         Some(p) => p.to_path_buf(),
         None => PathBuf::new(),
@@ -95,23 +371,51 @@ pub fn launch_tarpaulin(config: &Config) -> Result<(TraceMap, bool), RunError> {
         Some(true)
     };
     cargo::core::enable_nightly_features();
+    // Offline mode is still an unstable flag in this version of cargo
+    let unstable_flags = if config.offline {
+        vec!["offline".to_string()]
+    } else {
+        vec![]
+    };
     // This shouldn't fail so no checking the error.
-    let _ = cargo_config.configure(0u32, flag_quiet, &None, false, false, &None, &[]);
+    let _ = cargo_config.configure(0u32, flag_quiet, &None, config.frozen, config.locked,
+                                   &None, &unstable_flags);
     
     let workspace = Workspace::new(config.manifest.as_path(), &cargo_config)
         .map_err(|e| {
             println!("Cargo error: {}", e);
             RunError::Manifest
         })?;
+    let members = workspace.members()
+                           .map(|p| {
+                               (p.name().to_string(), normalize_root(p.root()))
+                           })
+                           .collect::<Vec<_>>();
     
-    setup_environment();
+    check_opt_level(&workspace, config);
     
-    let mut copt = ops::CompileOptions::new(&cargo_config, CompileMode::Test)
+    // Examples are ran as normal binaries instead of built with the test harness
+    let mode = if config.example.is_some() {
+        CompileMode::Build
+    } else {
+        CompileMode::Test
+    };
+    let mut copt = ops::CompileOptions::new(&cargo_config, mode)
         .map_err(|_| RunError::Cargo)?;
-    if let ops::CompileFilter::Default{ref mut required_features_filterable} = copt.filter {
+    if let Some(jobs) = config.jobs {
+        copt.build_config.jobs = jobs;
+    }
+    copt.build_config.requested_target = config.target.clone();
+    if let Some(ref example) = config.example {
+        copt.filter = ops::CompileFilter::new(false, vec![], false, vec![], false,
+                                              vec![example.clone()], false, vec![], false, false);
+    } else if config.all_targets {
+        copt.filter = ops::CompileFilter::new(false, vec![], false, vec![], false,
+                                              vec![], false, vec![], false, true);
+    } else if let ops::CompileFilter::Default{ref mut required_features_filterable} = copt.filter {
         *required_features_filterable = true;
     }
-    copt.features = config.features.clone();
+    copt.features = features.to_vec();
     copt.all_features = config.all_features;
     copt.no_default_features = config.no_default_features;
     copt.spec = match ops::Packages::from_flags(config.all, 
@@ -126,7 +430,7 @@ pub fn launch_tarpaulin(config: &Config) -> Result<(TraceMap, bool), RunError> {
     if config.verbose {
         println!("Running Tarpaulin");
     }
-    if !config.skip_clean {
+    if clean && !(config.skip_clean || config.reuse_build || config.binary.is_some() || config.attach.is_some()) {
         if config.verbose {
             println!("Cleaning project");
         }
@@ -140,30 +444,81 @@ pub fn launch_tarpaulin(config: &Config) -> Result<(TraceMap, bool), RunError> {
         };
         let _ = ops::clean(&workspace, &clean_opt);
     }
-    let mut result = TraceMap::new();
+    if let Some(pid) = config.attach {
+        let (coverage, tp) = attach_test(&workspace, pid, config)?;
+        return Ok((coverage, members, tp));
+    }
+    if let Some(ref binary) = config.binary {
+        if !binary.is_file() {
+            println!("Test binary {} doesn't exist", binary.display());
+            return Err(RunError::TestCompileFailed);
+        }
+        let tests = vec![find_binary_target(&workspace, binary)];
+        run_hook("pre-test", &config.pre_test_script, config)?;
+        let (coverage, tp) = run_tests(&workspace, &tests, config);
+        return Ok((coverage, members, tp));
+    }
+    if config.reuse_build {
+        println!("Reusing existing build");
+        let tests = find_existing_tests(&workspace, config);
+        if tests.is_empty() {
+            println!("No existing test binaries found, build the tests first");
+            return Err(RunError::TestCompileFailed);
+        }
+        run_hook("pre-test", &config.pre_test_script, config)?;
+        let (coverage, tp) = run_tests(&workspace, &tests, config);
+        return Ok((coverage, members, tp));
+    }
+    if config.runner == Runner::Nextest && config.example.is_none() {
+        // Nextest builds the tests itself
+        let (coverage, tp) = run_nextest(&workspace, features, config)?;
+        return Ok((coverage, members, tp));
+    }
     println!("Building project");
-    let compilation = ops::compile(&workspace, &copt);
-    let mut test_passed = true;
+    let compilation = {
+        // Cargo shows its own progress when verbose
+        let _progress = if config.verbose {
+            None
+        } else {
+            Some(Progress::spinner(config, "Compiling"))
+        };
+        timings::time("compile", || ops::compile(&workspace, &copt))
+    };
     match compilation {
         Ok(comp) => {
-            for &(ref package, ref _target_kind, ref name, ref path) in &comp.tests {
-                if config.verbose {
-                    println!("Processing {}", name);
-                }
-                if let Some((res, tp)) = get_test_coverage(&workspace, package, path.as_path(), config, false) {
-                    result.merge(&res);
-                    test_passed &= tp;
-                }
-                if config.run_ignored {
-                    if let Some((res, tp)) = get_test_coverage(&workspace, package, path.as_path(),
-                                                         config, true) {
-                        result.merge(&res);
-                        test_passed &= tp;
-                    }
+            run_hook("post-build", &config.post_build_script, config)?;
+            run_hook("pre-test", &config.pre_test_script, config)?;
+            if let Some(ref example) = config.example {
+                let examples = find_examples(&workspace, Some(example), &comp.binaries);
+                if examples.is_empty() {
+                    println!("Failed to find example {}", example);
+                    return Err(RunError::TestCompileFailed);
                 }
+                let (coverage, tp) = run_tests(&workspace, &examples, config);
+                return Ok((coverage, members, tp));
+            }
+            if comp.tests.is_empty() {
+                println!("Warning: no test binaries found; did you forget #[test]?");
+            }
+            let (mut coverage, mut tp) = run_tests(&workspace, &comp.tests, config);
+            if config.all_targets {
+                // Examples that aren't tests are only built so they're in the
+                // binaries rather than the tests
+                let examples = find_examples(&workspace, None, &comp.binaries)
+                    .into_iter()
+                    .filter(|e| !comp.tests.iter().any(|t| t.1 == e.1 && t.2 == e.2))
+                    .collect::<Vec<_>>();
+                let (res, examples_passed) = run_examples(&workspace, &examples, config);
+                coverage.unit.merge(&res);
+                tp &= examples_passed;
+            }
+            if config.doc {
+                let packages = copt.spec.get_packages(&workspace).map_err(|_| RunError::Packages)?;
+                let (doc, doc_passed) = run_doctests(&workspace, &packages, features, config);
+                coverage.unit.merge(&doc);
+                tp &= doc_passed;
             }
-            result.dedup();
-            Ok((result, test_passed))
+            Ok((coverage, members, tp))
         },
         Err(e) => {
             if config.verbose{
@@ -175,10 +530,534 @@ pub fn launch_tarpaulin(config: &Config) -> Result<(TraceMap, bool), RunError> {
 }
 
 
-fn setup_environment() {
+/// Runs a user provided hook script with the shell from the project root,
+/// failing the run if it doesn't succeed
+fn run_hook(name: &str, script: &Option<String>, config: &Config) -> Result<(), RunError> {
+    let script = match *script {
+        Some(ref s) => s,
+        None => return Ok(()),
+    };
+    println!("Running {} script: {}", name, script);
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg(script);
+    if let Some(root) = config.manifest.parent() {
+        cmd.current_dir(root);
+    }
+    match cmd.status() {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => {
+            println!("The {} script failed: {}", name, status);
+            Err(RunError::Hook)
+        },
+        Err(e) => {
+            println!("Failed to run the {} script: {}", name, e);
+            Err(RunError::Hook)
+        },
+    }
+}
+
+
+/// Builds the doctests of the packages with rustdoc keeping the compiled
+/// doctests without running them, then traces each of them. Doctests link the library built with
+/// the tarpaulin flags so its lines map back to the project sources and merge
+/// with the unit test coverage. Keeping the doctests needs a nightly rustdoc.
+fn run_doctests(workspace: &Workspace,
+                packages: &[&Package],
+                features: &[String],
+                config: &Config) -> (TraceMap, bool) {
+    let mut result = TraceMap::new();
+    let mut test_passed = true;
+    let doctests = workspace.target_dir().join("doctests").into_path_unlocked();
+    for package in packages.iter().filter(|p| p.targets().iter().any(|t| t.is_lib())) {
+        let dir = doctests.join(package.name().as_str());
+        let _ = fs::remove_dir_all(&dir);
+        let mut flags = env::var("RUSTFLAGS").unwrap_or_default();
+        // The doctests are only built, they're ran when traced below
+        flags.push_str(&format!(" -Z unstable-options --persist-doctests {} --no-run", dir.display()));
+        let mut cmd = Command::new("cargo");
+        cmd.args(&["test", "--doc", "--manifest-path"])
+           .arg(package.manifest_path())
+           .env("RUSTDOCFLAGS", flags);
+        if !features.is_empty() {
+            cmd.arg("--features").arg(features.join(" "));
+        }
+        if config.all_features {
+            cmd.arg("--all-features");
+        }
+        if config.no_default_features {
+            cmd.arg("--no-default-features");
+        }
+        if !config.verbose {
+            cmd.stdout(Stdio::null()).stderr(Stdio::null());
+        }
+        if config.verbose {
+            println!("Building doctests of {}", package.name());
+        }
+        match cmd.status() {
+            Ok(status) if status.success() => {},
+            Ok(_) => {
+                println!("Failed to build the doctests of {}", package.name());
+                test_passed = false;
+                continue;
+            },
+            Err(e) => {
+                println!("Failed to run the doctests of {}: {}", package.name(), e);
+                test_passed = false;
+                continue;
+            },
+        }
+        // Each doctest is kept in a directory named after its file and line
+        let binaries = WalkDir::new(&dir).into_iter()
+                                         .filter_map(|e| e.ok())
+                                         .filter(|e| e.file_name() == "rust_out")
+                                         .map(|e| e.path().to_path_buf())
+                                         .collect::<Vec<_>>();
+        if binaries.is_empty() && config.verbose {
+            println!("No doctests of {} were found", package.name());
+        }
+        for binary in &binaries {
+            if config.verbose {
+                println!("Processing doctest {}", binary.display());
+            }
+            if let Some((res, tp)) = get_doctest_coverage(workspace, package, binary, config) {
+                result.merge(&res);
+                test_passed &= tp;
+            }
+        }
+    }
+    (result, test_passed)
+}
+
+
+/// Runs the given test executables collecting and merging their coverage
+fn run_tests(workspace: &Workspace,
+             tests: &[(Package, TargetKind, String, PathBuf)],
+             config: &Config) -> (CoverageBreakdown, bool) {
+    let mut result = CoverageBreakdown::new();
+    let mut test_passed = true;
+    let mut found_test = false;
+    reset_tests_found();
+    // Only tests ran on their own can pass by aborting, the analysis for them
+    // is done once for all the executables
+    let should_panic = if config.runner == Runner::Isolated || config.test_name.is_some() {
+        should_panic_tests(workspace, config)
+    } else {
+        HashSet::new()
+    };
+    let progress = Progress::bar(config, tests.len() as u64);
+    for (i, &(ref package, ref target_kind, ref name, ref path)) in tests.iter().enumerate() {
+        progress.set_message(&format!("tracing binary {} of {}: {}", i + 1, tests.len(), name));
+        if i > 0 {
+            progress.inc();
+        }
+        if is_interrupted() {
+            println!("Interrupted, reporting the coverage collected so far");
+            test_passed = false;
+            break;
+        }
+        if config.verbose {
+            println!("Processing {}", name);
+        }
+        let coverage = if *target_kind == TargetKind::Test {
+            &mut result.integration
+        } else {
+            &mut result.unit
+        };
+        if config.example.is_some() {
+            if let Some((res, tp)) = get_example_coverage(workspace, package, path.as_path(), config) {
+                coverage.merge(&res);
+                test_passed &= tp;
+            }
+            continue;
+        }
+        if let Some(ref test_name) = config.test_name {
+            // Only the executables with the test are traced so the results
+            // are just what the test ran
+            if uses_harness(package, target_kind, name) {
+                if let Some((res, tp)) = get_named_test_coverage(workspace, package, path.as_path(),
+                                                                 test_name, &should_panic, config) {
+                    coverage.merge(&res);
+                    test_passed &= tp;
+                    found_test = true;
+                }
+            }
+            continue;
+        }
+        if !uses_harness(package, target_kind, name) {
+            // Custom test runners don't accept the libtest arguments
+            if let Some((res, tp)) = get_coverage(workspace, package, path.as_path(), config, false, false, None, None) {
+                coverage.merge(&res);
+                test_passed &= tp;
+            }
+            continue;
+        }
+        if config.runner == Runner::Isolated {
+            let (res, tp) = get_isolated_coverage(workspace, package, path.as_path(), &should_panic, config);
+            coverage.merge(&res);
+            test_passed &= tp;
+            continue;
+        }
+        if let Some((res, tp)) = get_test_coverage(workspace, package, path.as_path(), config, false) {
+            coverage.merge(&res);
+            test_passed &= tp;
+        }
+        if config.run_ignored {
+            if let Some((res, tp)) = get_test_coverage(workspace, package, path.as_path(),
+                                                 config, true) {
+                coverage.merge(&res);
+                test_passed &= tp;
+            }
+        }
+    }
+    if config.granularity != Granularity::Instruction {
+        timings::time("merge", || {
+            result.unit.dedup();
+            result.integration.dedup();
+        });
+    }
+    if let Some(ref test_name) = config.test_name {
+        if !found_test {
+            println!("Warning: no test named {} was found in the test binaries", test_name);
+        }
+    }
+    let harnessed = config.example.is_none() &&
+                    tests.iter().any(|&(ref p, ref k, ref n, _)| uses_harness(p, k, n));
+    if harnessed && config.trace_plan.is_none() && !tests_found() {
+        println!("Warning: no test functions were found in the test binaries, the tests may be \
+                  behind a feature that isn't enabled or outside of a module named by --test-namespace");
+    }
+    (result, test_passed)
+}
+
+
+/// Runs the tests through cargo nextest, which runs every test in its own
+/// process. The test executables are found with `cargo nextest list` and
+/// traced one at a time by running nextest on just that executable.
+fn run_nextest(workspace: &Workspace,
+               features: &[String],
+               config: &Config) -> Result<(CoverageBreakdown, bool), RunError> {
+    println!("Building project with cargo nextest");
+    let binaries = list_nextest_binaries(features, config).map_err(|e| {
+        println!("Failed to list the tests with cargo nextest: {}", e);
+        RunError::TestCompileFailed
+    })?;
+    run_hook("post-build", &config.post_build_script, config)?;
+    run_hook("pre-test", &config.pre_test_script, config)?;
+    if config.doc {
+        println!("Warning: cargo nextest doesn't run doctests, --doc is ignored with --runner nextest");
+    }
+    let mut result = CoverageBreakdown::new();
+    let mut test_passed = true;
+    let progress = Progress::bar(config, binaries.len() as u64);
+    for (i, &(ref path, ref binary_id, ref kind)) in binaries.iter().enumerate() {
+        progress.set_message(&format!("tracing binary {} of {}: {}", i + 1, binaries.len(), binary_id));
+        if i > 0 {
+            progress.inc();
+        }
+        if is_interrupted() {
+            println!("Interrupted, reporting the coverage collected so far");
+            test_passed = false;
+            break;
+        }
+        if config.verbose {
+            println!("Processing {}", binary_id);
+        }
+        let (package, _, _, _) = find_binary_target(workspace, path);
+        let coverage = if kind == "test" {
+            &mut result.integration
+        } else {
+            &mut result.unit
+        };
+        match get_nextest_coverage(workspace, &package, path, binary_id, features, config) {
+            Some((res, tp)) => {
+                coverage.merge(&res);
+                test_passed &= tp;
+            },
+            None => test_passed = false,
+        }
+    }
+    if config.granularity != Granularity::Instruction {
+        timings::time("merge", || {
+            result.unit.dedup();
+            result.integration.dedup();
+        });
+    }
+    if binaries.is_empty() {
+        println!("Warning: cargo nextest found no tests to run");
+    }
+    Ok((result, test_passed))
+}
+
+
+/// Traces an already running test executable from when it's attached to until
+/// it exits. The executable is found through procfs and mapped to the target
+/// it was built from like `--binary`.
+fn attach_test(workspace: &Workspace, pid: i32, config: &Config) -> Result<(CoverageBreakdown, bool), RunError> {
+    let exe = fs::read_link(format!("/proc/{}/exe", pid)).map_err(|e| {
+        println!("Failed to find the executable of process {}: {}", pid, e);
+        RunError::Attach
+    })?;
+    let (package, target_kind, name, path) = find_binary_target(workspace, &exe);
+    let pid = Pid::from_raw(pid);
+    match attach_to(pid) {
+        Ok(threads) => println!("Attached to {} running {} with {} threads", pid, exe.display(), threads.len()),
+        Err(e) => {
+            println!("Failed to attach to process {}: {}", pid, e);
+            return Err(RunError::Attach);
+        },
+    }
+    let mut result = CoverageBreakdown::new();
+    let harness = uses_harness(&package, &target_kind, &name);
+    let test_passed = match collect_coverage(workspace, &package, &path, pid, harness, None, None, config) {
+        Ok((res, tp)) => {
+            if target_kind == TargetKind::Test {
+                result.integration.merge(&res);
+            } else {
+                result.unit.merge(&res);
+            }
+            tp
+        },
+        Err(e) => {
+            println!("Error occurred: {}", e);
+            false
+        },
+    };
+    if config.granularity != Granularity::Instruction {
+        result.unit.dedup();
+        result.integration.dedup();
+    }
+    Ok((result, test_passed))
+}
+
+
+/// Checks the package metadata to see if the target is built with the libtest
+/// harness, targets with `harness = false` provide their own main.
+fn uses_harness(package: &Package, kind: &TargetKind, name: &str) -> bool {
+    package.targets()
+           .iter()
+           .find(|t| t.kind() == kind && t.name() == name)
+           .map(|t| t.harness())
+           .unwrap_or(true)
+}
+
+
+/// Finds the package and binary of the named example in the compiled binaries,
+/// without a name every example with a binary is found
+fn find_examples(workspace: &Workspace,
+                 example: Option<&str>,
+                 binaries: &[PathBuf]) -> Vec<(Package, TargetKind, String, PathBuf)> {
+    let mut result = vec![];
+    for package in workspace.members() {
+        let targets = package.targets()
+                             .iter()
+                             .filter(|t| t.is_example() && example.map_or(true, |e| t.name() == e));
+        for target in targets {
+            let binary = binaries.iter()
+                                 .find(|b| b.file_stem().and_then(|x| x.to_str()) == Some(target.name()));
+            if let Some(binary) = binary {
+                result.push((package.clone(), target.kind().clone(), target.name().to_string(), binary.clone()));
+            }
+        }
+        if example.is_some() && !result.is_empty() {
+            break;
+        }
+    }
+    result
+}
+
+
+/// Traces examples built as plain binaries rather than tests, they're ran
+/// without the test harness arguments like `--example`
+fn run_examples(workspace: &Workspace,
+                examples: &[(Package, TargetKind, String, PathBuf)],
+                config: &Config) -> (TraceMap, bool) {
+    let mut result = TraceMap::new();
+    let mut test_passed = true;
+    for &(ref package, _, ref name, ref path) in examples {
+        if is_interrupted() {
+            test_passed = false;
+            break;
+        }
+        if config.verbose {
+            println!("Processing example {}", name);
+        }
+        if let Some((res, tp)) = get_example_coverage(workspace, package, path.as_path(), config) {
+            result.merge(&res);
+            test_passed &= tp;
+        }
+    }
+    if config.granularity != Granularity::Instruction {
+        result.dedup();
+    }
+    (result, test_passed)
+}
+
+
+/// Looks in the target directory for test executables left by a previous
+/// build. Executables are matched to workspace members by their crate name and
+/// only kept if they contain a libtest generated main.
+fn find_existing_tests(workspace: &Workspace, config: &Config) -> Vec<(Package, TargetKind, String, PathBuf)> {
+    let mut deps = workspace.target_dir().into_path_unlocked();
+    // Cross compiled builds have a directory for the target triple
+    if let Some(ref target) = config.target {
+        deps.push(target);
+    }
+    let deps = deps.join("debug").join("deps");
+    let mut result = vec![];
+    let entries = match deps.read_dir() {
+        Ok(e) => e,
+        Err(_) => return result,
+    };
+    for entry in entries.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if path.extension().is_some() || !is_test_executable(&path, config) {
+            continue;
+        }
+        if let Some(test) = match_binary_target(workspace, &path) {
+            result.push(test);
+        }
+    }
+    result
+}
+
+
+/// Matches an executable built by cargo to the workspace member and target
+/// it was built from using the crate name before the hash in the file name.
+fn match_binary_target(workspace: &Workspace, path: &Path) -> Option<(Package, TargetKind, String, PathBuf)> {
+    let stem = path.file_name().and_then(|x| x.to_str())?;
+    let stem = stem.rsplitn(2, '-').last().unwrap_or(stem);
+    for package in workspace.members() {
+        if let Some(target) = package.targets().iter().find(|t| t.crate_name() == stem) {
+            return Some((package.clone(),
+                         target.kind().clone(),
+                         target.name().to_string(),
+                         path.to_path_buf()));
+        }
+    }
+    None
+}
+
+
+/// Finds the target for a prebuilt binary given by the user. If it doesn't
+/// match any target the binary is treated as the current package's tests.
+fn find_binary_target(workspace: &Workspace, binary: &Path) -> (Package, TargetKind, String, PathBuf) {
+    match_binary_target(workspace, binary).unwrap_or_else(|| {
+        let package = workspace.current()
+                               .ok()
+                               .or_else(|| workspace.members().next())
+                               .cloned()
+                               .expect("workspace has no members");
+        let name = binary.file_name()
+                         .map(|x| x.to_string_lossy().into_owned())
+                         .unwrap_or_default();
+        (package, TargetKind::Lib(vec![]), name, binary.to_path_buf())
+    })
+}
+
+
+/// Checks the binary is executable and was built by the test harness
+fn is_test_executable(path: &Path, config: &Config) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    use object::{Object, File as OFile};
+    use rustc_demangle::demangle;
+
+    let is_exec = path.metadata()
+                      .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+                      .unwrap_or(false);
+    if !is_exec {
+        return false;
+    }
+    let file = match std::fs::File::open(path) {
+        Ok(f) => f,
+        Err(_) => return false,
+    };
+    let file = match unsafe { memmap::MmapOptions::new().map(&file) } {
+        Ok(f) => f,
+        Err(_) => return false,
+    };
+    match OFile::parse(&*file) {
+        Ok(obj) => obj.symbols()
+                      .filter_map(|s| s.name())
+                      .any(|n| config.is_generated_main(&format!("{:#}", demangle(n)))),
+        Err(_) => false,
+    }
+}
+
+
+/// Gets the opt-level the test profile builds with from the workspace manifest,
+/// the test profile inherits from the dev profile
+fn profile_opt_level(manifest: &Path) -> Option<String> {
+    let contents = std::fs::read_to_string(manifest).ok()?;
+    let manifest = contents.parse::<toml::Value>().ok()?;
+    let profiles = manifest.get("profile")?;
+    ["test", "dev"].iter()
+                   .filter_map(|p| profiles.get(p).and_then(|p| p.get("opt-level")))
+                   .map(|level| match *level {
+                       toml::Value::String(ref s) => s.clone(),
+                       ref other => other.to_string(),
+                   })
+                   .next()
+}
+
+/// Warns if the tests are built with optimisations. Optimised code breaks the
+/// assumption of one address per line and contiguous functions so reduces
+/// the accuracy of the results.
+fn check_opt_level(workspace: &Workspace, config: &Config) {
+    if let Some(level) = profile_opt_level(&workspace.root().join("Cargo.toml")) {
+        if level != "0" {
+            if config.reuse_build {
+                println!("Warning: the test profile uses opt-level {} which reduces coverage \
+                          accuracy, rebuild without --reuse-build to use opt-level 0", level);
+            } else {
+                println!("Warning: the test profile uses opt-level {} which reduces coverage \
+                          accuracy, overriding it with -C opt-level=0", level);
+            }
+        }
+    }
+    // User RUSTFLAGS come after tarpaulin's so take precedence
+    let user_level = env::var("RUSTFLAGS").ok().and_then(|flags| {
+        flags.split_whitespace()
+             .filter_map(|f| f.split("opt-level=").nth(1))
+             .filter(|level| *level != "0")
+             .last()
+             .map(ToString::to_string)
+    });
+    if let Some(level) = user_level {
+        println!("Warning: RUSTFLAGS sets opt-level={} which reduces coverage accuracy", level);
+    }
+}
+
+/// Checks whether the rustc cargo builds with is a nightly or locally built
+/// toolchain, which are the ones accepting unstable `-Z` flags
+fn is_nightly() -> bool {
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    Command::new(rustc)
+        .arg("--version")
+        .output()
+        .map(|o| {
+            let version = String::from_utf8_lossy(&o.stdout);
+            version.contains("nightly") || version.contains("dev")
+        })
+        .unwrap_or(false)
+}
+
+fn setup_environment(config: &Config) {
     let rustflags = "RUSTFLAGS";
     let mut value = " -C relocation-model=dynamic-no-pic -C link-dead-code -C opt-level=0 ".to_string();
+    if config.cfg_tarpaulin {
+        value.push_str("--cfg tarpaulin ");
+    }
+    if config.macro_coverage == MacroCoverage::Def {
+        // Without this rustc collapses macro expansions onto the invocation
+        value.push_str("-Z debug-macros ");
+    }
     if let Ok(vtemp) = env::var(rustflags) {
+        // Tarpaulin's flags are already there if the project has been launched
+        // before in this process, adding them again would change RUSTFLAGS and
+        // make cargo rebuild everything
+        if vtemp.starts_with(&value) {
+            return;
+        }
         value.push_str(vtemp.as_ref());
     }
     env::set_var(rustflags, value);
@@ -208,65 +1087,386 @@ fn accumulate_lines((mut acc, mut group): (Vec<String>, Vec<u64>), next: u64) ->
     }
 }
 
-/// Reports the test coverage using the users preferred method. See config.rs
-/// or help text for details.
-pub fn report_coverage(config: &Config, result: &TraceMap) {
-    if !result.is_empty() {
-        println!("Coverage Results");
-        if config.verbose {
+/// Gets the covered and coverable amount of the test code, the lines in the
+/// tests directory or in a test namespace
+fn test_code_coverage(result: &TraceMap, config: &Config) -> (usize, usize) {
+    let mut covered = 0;
+    let mut coverable = 0;
+    for (file, traces) in result.iter() {
+        let in_tests_dir = config.strip_project_path(file).starts_with("tests");
+        for t in traces {
+            let in_test_module = result.get_module(file, t.line)
+                                       .map_or(false, |m| config.is_test_function(&format!("{}::", m)));
+            if in_tests_dir || in_test_module {
+                covered += amount_covered(&[t]);
+                coverable += amount_coverable(&[t]);
+            }
+        }
+    }
+    (covered, coverable)
+}
 
-            println!();
-            println!("Uncovered Lines:");
-            for (ref key, ref value) in result.iter() {
-                let path = config.strip_project_path(key);
-                let mut uncovered_lines = vec![];
-                for v in value.iter() {
-                    match v.stats {
-                        traces::CoverageStat::Line(count) if count == 0 => {
-                            uncovered_lines.push(v.line);
-                        },
-                        _ => (),
-                    }
-                }
-                uncovered_lines.sort();
-                let (groups, last_group) =
-                    uncovered_lines.into_iter()
-                    .fold((vec![], vec![]), accumulate_lines);
-                let (groups, _) = accumulate_lines((groups, last_group), u64::max_value());
-                if ! groups.is_empty() {
-                    println!("{}: {}", path.display(), groups.join(", "));
+/// Prints the uncovered lines when verbose, the coverage of each file or
+/// module and the total coverage
+fn print_summary(config: &Config, result: &TraceMap) {
+    println!("Coverage Results");
+    if config.verbose {
+
+        println!();
+        println!("Uncovered Lines:");
+        for (ref key, ref value) in result.iter() {
+            let path = config.strip_project_path(key);
+            let mut uncovered_lines = vec![];
+            for v in value.iter() {
+                match v.stats {
+                    traces::CoverageStat::Line(count) if count == 0 => {
+                        uncovered_lines.push(v.line);
+                    },
+                    _ => (),
                 }
             }
-            println!();
+            uncovered_lines.sort();
+            let (groups, last_group) =
+                uncovered_lines.into_iter()
+                .fold((vec![], vec![]), accumulate_lines);
+            let (groups, _) = accumulate_lines((groups, last_group), u64::max_value());
+            if ! groups.is_empty() {
+                println!("{}: {}", path.display(), groups.join(", "));
+            }
         }
+        println!();
+    }
+    if config.granularity == Granularity::Function {
+        println!("Tested/Total Functions:");
+    } else {
         println!("Tested/Total Lines:");
-        for file in result.files() {
-            let path = config.strip_project_path(file);
-            println!("{}: {}/{}", path.display(), result.covered_in_path(&file), result.coverable_in_path(&file));
+    }
+    match config.group_by {
+        GroupBy::File => {
+            for file in result.files() {
+                let path = config.strip_project_path(file);
+                println!("{}: {}/{}", path.display(), result.covered_in_path(&file), result.coverable_in_path(&file));
+            }
+        },
+        GroupBy::Module => {
+            for (module, (covered, coverable)) in result.module_coverage() {
+                let percent = coverage_percentage(covered, coverable) * 100.0f64;
+                println!("{}: {:.prec$}% {}/{}", module, percent, covered, coverable,
+                         prec = config.precision());
+            }
+        },
+    }
+    let percent = result.coverage_percentage() * 100.0f64;
+    println!("\n{:.prec$}% coverage, {}/{} {} covered", percent,
+             result.total_covered(), result.total_coverable(), config.granularity.counted(),
+             prec = config.precision());
+    if config.count_test_lines {
+        let (covered, coverable) = test_code_coverage(result, config);
+        println!("{:.prec$}% test code coverage, {}/{} {} covered",
+                 coverage_percentage(covered, coverable) * 100.0f64,
+                 covered, coverable, config.granularity.counted(),
+                 prec = config.precision());
+    }
+}
+
+/// Reports the test coverage using the users preferred method. See config.rs
+/// or help text for details. The summary is printed unless `--quiet` is given
+/// without `--out Stdout`, alongside any reports written to files.
+pub fn report_coverage(config: &Config, result: &TraceMap) {
+    let stdout = config.generate.iter().any(|g| match *g {
+        OutputFile::Stdout => true,
+        _ => false,
+    });
+    if !result.is_empty() {
+        if stdout || !config.quiet {
+            print_summary(config, result);
         }
-        let percent = result.coverage_percentage() * 100.0f64;
-        // Put file filtering here
-        println!("\n{:.2}% coverage, {}/{} lines covered", percent,
-                 result.total_covered(), result.total_coverable());
-        if config.is_coveralls() {
-            report::coveralls::export(result, config);
-            println!("Coverage data sent");
+        if config.summary_format == SummaryFormat::Machine {
+            println!("coverage: {}/{} ({:.2}%)", result.total_covered(),
+                     result.total_coverable(), result.coverage_percentage() * 100.0f64);
         }
-
-        for g in &config.generate {
-            match *g {
-                OutputFile::Xml => {
-                    report::cobertura::export(result, config);
-                },
-                _ => {
-                    println!("Format currently unsupported");
-                },
+        if config.is_coveralls() {
+            // Upload failures are always shown, a missing report is otherwise
+            // only noticed on the coveralls website
+            match report::coveralls::send(result, config) {
+                Ok(()) => println!("Coverage data sent"),
+                Err(e) => println!("Coveralls send failed. {}", e),
             }
         }
     } else {
         println!("No coverage results collected.");
+        if config.summary_format == SummaryFormat::Machine {
+            println!("coverage: 0/0 (0.00%)");
+        }
     }
+    // Reports are written even when empty so tooling expecting them finds a
+    // valid file
+    for g in &config.generate {
+        match *g {
+            OutputFile::Xml => {
+                report::cobertura::export(result, config);
+            },
+            OutputFile::Json => {
+                report::json::export(result, config);
+            },
+            OutputFile::JsonLines => {
+                report::json::export_lines(result, config);
+            },
+            OutputFile::Sqlite => {
+                report::sqlite::export(result, config);
+            },
+            OutputFile::Coveralls => {
+                report::coveralls::export(result, config);
+            },
+            OutputFile::Codecov => {
+                report::codecov::export(result, config);
+            },
+            OutputFile::Html => {
+                report::html::export(result, config);
+            },
+            OutputFile::Toml => {
+                report::toml::export(result, config);
+            },
+            // Printed with the summary above
+            OutputFile::Stdout => {},
+        }
+    }
+}
+
+fn format_lines(lines: Vec<u64>) -> String {
+    let (groups, last_group) = lines.into_iter().fold((vec![], vec![]), accumulate_lines);
+    let (groups, _) = accumulate_lines((groups, last_group), u64::max_value());
+    groups.join(", ")
+}
 
+/// Prints how coverage changed since the baseline report was saved, overall,
+/// for each file and the lines which became covered or missed.
+pub fn report_delta(config: &Config, result: &TraceMap, baseline: &Path) {
+    let before = match report::json::load_line_coverage(baseline) {
+        Ok(b) => b,
+        Err(e) => {
+            println!("Failed to load baseline report {}: {}", baseline.display(), e);
+            return;
+        },
+    };
+    let after = report::json::line_coverage(result, config);
+    let empty = BTreeMap::new();
+    let counts = |lines: &BTreeMap<u64, bool>| {
+        (lines.values().filter(|&&x| x).count(), lines.len())
+    };
+    let percent = |(covered, total): (usize, usize)| coverage_percentage(covered, total) * 100.0f64;
+    let files = before.keys().chain(after.keys()).collect::<BTreeSet<_>>();
+    let mut total_before = (0, 0);
+    let mut total_after = (0, 0);
+    println!("Coverage change since {}:", baseline.display());
+    for file in files {
+        let old = before.get(file).unwrap_or(&empty);
+        let new = after.get(file).unwrap_or(&empty);
+        let (old_counts, new_counts) = (counts(old), counts(new));
+        total_before = (total_before.0 + old_counts.0, total_before.1 + old_counts.1);
+        total_after = (total_after.0 + new_counts.0, total_after.1 + new_counts.1);
+        let covered = new.iter()
+                         .filter(|&(l, &hit)| hit && !old.get(l).cloned().unwrap_or(false))
+                         .map(|(l, _)| *l)
+                         .collect::<Vec<_>>();
+        let missed = new.iter()
+                        .filter(|&(l, &hit)| !hit && old.get(l).cloned().unwrap_or(false))
+                        .map(|(l, _)| *l)
+                        .collect::<Vec<_>>();
+        if old_counts == new_counts && covered.is_empty() && missed.is_empty() {
+            continue;
+        }
+        let (old_percent, new_percent) = (percent(old_counts), percent(new_counts));
+        println!("{}: {:.prec$}% -> {:.prec$}% ({:+.prec$}%)", file.display(), old_percent,
+                 new_percent, new_percent - old_percent, prec = config.precision());
+        if !covered.is_empty() {
+            println!("    newly covered: {}", format_lines(covered));
+        }
+        if !missed.is_empty() {
+            println!("    newly missed: {}", format_lines(missed));
+        }
+    }
+    let (old_percent, new_percent) = (percent(total_before), percent(total_after));
+    println!("Overall: {:.prec$}% -> {:.prec$}% ({:+.prec$}%)", old_percent, new_percent,
+             new_percent - old_percent, prec = config.precision());
+}
+
+/// Fails if the overall coverage is below that of the baseline report. With
+/// `--update-baseline` the results are saved as the new baseline when coverage
+/// increased so it can be committed, and become the baseline if there's none.
+fn ratchet(config: &Config, result: &TraceMap, baseline: &Path) -> Result<(), RunError> {
+    let percent = result.coverage_percentage() * 100.0f64;
+    let save = || if !config.update_baseline {
+        println!("Rerun with --update-baseline to save the coverage to {}", baseline.display());
+    } else if let Err(e) = report::json::save(result, config, baseline) {
+        println!("Failed to save ratchet baseline {}: {}", baseline.display(), e);
+    };
+    match report::json::load_coverage(baseline) {
+        Ok(previous) => {
+            let previous = previous * 100.0f64;
+            // The saved coverage may not parse back to exactly the same float
+            let change = percent - previous;
+            if change < -RATCHET_TOLERANCE {
+                println!("Coverage of {:.prec$}% is below the ratchet baseline of {:.prec$}%", percent, previous,
+                         prec = config.precision());
+                return Err(RunError::Ratchet);
+            } else if change > RATCHET_TOLERANCE {
+                println!("Coverage increased from {:.prec$}% to {:.prec$}%", previous, percent,
+                         prec = config.precision());
+                save();
+            }
+        },
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+            println!("No ratchet baseline at {}", baseline.display());
+            save();
+        },
+        Err(e) => {
+            println!("Failed to load ratchet baseline {}: {}", baseline.display(), e);
+            return Err(RunError::Ratchet);
+        },
+    }
+    Ok(())
+}
+
+/// Fails listing the lines covered in the baseline report which aren't
+/// covered now. Lines which are no longer coverable aren't regressions.
+fn check_regressions(config: &Config, result: &TraceMap, baseline: &Path) -> Result<(), RunError> {
+    let before = match report::json::load_line_coverage(baseline) {
+        Ok(b) => b,
+        Err(e) => {
+            println!("Failed to load baseline report {}: {}", baseline.display(), e);
+            return Err(RunError::Regression);
+        },
+    };
+    let regressions = find_regressions(&before, &report::json::line_coverage(result, config));
+    if regressions.is_empty() {
+        return Ok(());
+    }
+    println!("Lines covered in {} are no longer covered:", baseline.display());
+    for (file, lines) in regressions {
+        println!("{}: {}", file.display(), format_lines(lines));
+    }
+    Err(RunError::Regression)
+}
+
+/// Finds the lines of each file covered before but not after
+pub fn find_regressions(before: &BTreeMap<PathBuf, BTreeMap<u64, bool>>,
+                        after: &BTreeMap<PathBuf, BTreeMap<u64, bool>>) -> Vec<(PathBuf, Vec<u64>)> {
+    after.iter()
+         .filter_map(|(file, lines)| {
+             let old = before.get(file)?;
+             let missed = lines.iter()
+                               .filter(|&(line, &hit)| !hit && old.get(line) == Some(&true))
+                               .map(|(&line, _)| line)
+                               .collect::<Vec<_>>();
+             if missed.is_empty() {
+                 None
+             } else {
+                 Some((file.clone(), missed))
+             }
+         })
+         .collect()
+}
+
+/// Returns the files with a coverage percentage below the threshold that
+/// aren't exempt from it.
+pub fn files_below_threshold(config: &Config, result: &TraceMap, threshold: f64) -> Vec<(PathBuf, f64)> {
+    result.files()
+          .into_iter()
+          .filter(|f| !config.exclude_from_file_threshold(f))
+          .filter(|f| result.coverable_in_path(f) > 0)
+          .map(|f| {
+              let percent = result.coverage_percentage_in_path(f) * 100.0f64;
+              (f.to_path_buf(), percent)
+          })
+          .filter(|&(_, percent)| percent < threshold)
+          .collect()
+}
+
+/// Groups the covered and coverable lines by the workspace member owning each
+/// file. A file belongs to the member with the deepest root containing it so
+/// nested members aren't counted in their parent.
+pub fn package_coverage(result: &TraceMap, members: &[PackageRoot]) -> Vec<(String, usize, usize)> {
+    let mut rows = members.iter()
+                          .map(|&(ref name, _)| (name.clone(), 0, 0))
+                          .collect::<Vec<_>>();
+    for file in result.files() {
+        let owner = members.iter()
+                           .enumerate()
+                           .filter(|&(_, &(_, ref root))| file.starts_with(root))
+                           .max_by_key(|&(_, &(_, ref root))| root.components().count())
+                           .map(|(i, _)| i);
+        if let Some(i) = owner {
+            rows[i].1 += result.covered_in_path(file);
+            rows[i].2 += result.coverable_in_path(file);
+        }
+    }
+    rows
+}
+
+/// Prints a table of the coverage of each workspace member followed by the
+/// total coverage.
+pub fn report_packages(config: &Config, result: &TraceMap, members: &[PackageRoot]) {
+    let rows = package_coverage(result, members);
+    let width = rows.iter()
+                    .map(|&(ref name, _, _)| name.len())
+                    .max()
+                    .unwrap_or(0)
+                    .max("Total".len());
+    println!("Package Coverage:");
+    for (name, covered, coverable) in rows {
+        println!("{:<width$} {:>7.prec$}% {}/{}", name, coverage_percentage(covered, coverable) * 100.0f64,
+                 covered, coverable, width = width, prec = config.precision());
+    }
+    println!("{:<width$} {:>7.prec$}% {}/{}", "Total", result.coverage_percentage() * 100.0f64,
+             result.total_covered(), result.total_coverable(), width = width,
+             prec = config.precision());
+}
+
+/// Reports the coverage of unit and integration tests separately as selected
+/// by the breakdown option.
+pub fn report_breakdown(config: &Config, breakdown: &CoverageBreakdown) {
+    let show_unit = match config.breakdown {
+        Some(Breakdown::Unit) | Some(Breakdown::Both) => true,
+        _ => false,
+    };
+    let show_integration = match config.breakdown {
+        Some(Breakdown::Integration) | Some(Breakdown::Both) => true,
+        _ => false,
+    };
+    if show_unit {
+        let unit = &breakdown.unit;
+        println!("{:.prec$}% unit test coverage, {}/{} {} covered",
+                 unit.coverage_percentage() * 100.0f64,
+                 unit.total_covered(), unit.total_coverable(), config.granularity.counted(),
+                 prec = config.precision());
+    }
+    if show_integration {
+        let integration = &breakdown.integration;
+        println!("{:.prec$}% integration test coverage, {}/{} {} covered",
+                 integration.coverage_percentage() * 100.0f64,
+                 integration.total_covered(), integration.total_coverable(),
+                 config.granularity.counted(), prec = config.precision());
+    }
+}
+
+/// A test ran on its own with `--exact`
+#[derive(Debug, Clone, Copy)]
+struct ExactTest<'a> {
+    /// Libtest name of the test
+    name: &'a str,
+    /// Whether the test is marked `#[should_panic]`, so with `panic=abort` it
+    /// passes by aborting
+    should_panic: bool,
+}
+
+impl<'a> ExactTest<'a> {
+    fn new(name: &'a str, should_panic: &HashSet<String>) -> Self {
+        ExactTest {
+            name,
+            should_panic: should_panic.contains(name),
+        }
+    }
 }
 
 /// Returns the coverage statistics for a test executable in the given workspace
@@ -275,12 +1475,287 @@ pub fn get_test_coverage(project: &Workspace,
                          test: &Path,
                          config: &Config,
                          ignored: bool) -> Option<(TraceMap, bool)> {
+    get_coverage(project, package, test, config, ignored, true, None, None)
+}
+
+/// Gets the test names from the terse `--list` output of a libtest executable,
+/// benchmarks and any other output are skipped
+fn parse_test_list(output: &str) -> Vec<String> {
+    output.lines()
+          .filter(|l| l.ends_with(": test"))
+          .map(|l| l[..l.len() - ": test".len()].to_string())
+          .collect()
+}
+
+/// Lists the tests in a libtest executable, with ignored only the ignored
+/// tests are listed. This is how nextest finds the tests to run.
+fn list_tests(test: &Path, package: &Package, ignored: bool) -> io::Result<Vec<String>> {
+    let mut cmd = Command::new(test);
+    cmd.args(&["--list", "--format", "terse"]);
+    if ignored {
+        cmd.arg("--ignored");
+    }
+    if let Some(parent) = package.manifest_path().parent() {
+        cmd.current_dir(parent);
+    }
+    let output = cmd.stderr(Stdio::null()).output()?;
+    Ok(parse_test_list(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Returns the coverage statistics for running only the test with the given
+/// name, if the test executable has it. Ignored tests are ran if named. The
+/// `#[should_panic]` tests decide whether the test passes if it aborts.
+pub fn get_named_test_coverage(project: &Workspace,
+                               package: &Package,
+                               test: &Path,
+                               name: &str,
+                               should_panic: &HashSet<String>,
+                               config: &Config) -> Option<(TraceMap, bool)> {
+    let has_test = |ignored| {
+        list_tests(test, package, ignored).map(|names| names.iter().any(|n| n == name))
+                                          .unwrap_or(false)
+    };
+    let exact = Some(ExactTest::new(name, should_panic));
+    if has_test(false) {
+        get_coverage(project, package, test, config, false, true, exact, None)
+    } else if has_test(true) {
+        get_coverage(project, package, test, config, true, true, exact, None)
+    } else {
+        None
+    }
+}
+
+/// Returns the coverage statistics for a test executable running each test in
+/// its own process as nextest does, merging the coverage of every process.
+/// The test fails if any of its tests fail, a test aborting only passes if
+/// it's one of the `#[should_panic]` tests. The executable's tracer data is
+/// only generated once and reused for every process.
+pub fn get_isolated_coverage(project: &Workspace,
+                             package: &Package,
+                             test: &Path,
+                             should_panic: &HashSet<String>,
+                             config: &Config) -> (TraceMap, bool) {
+    let mut result = TraceMap::new();
+    let mut test_passed = true;
+    let traces = match load_tracemap(project, package, test, true, config) {
+        Ok(traces) => traces,
+        Err(e) => {
+            println!("Error occurred: {}", e);
+            return (result, false);
+        },
+    };
+    let mut runs = vec![false];
+    if config.run_ignored {
+        runs.push(true);
+    }
+    for ignored in runs {
+        let names = match list_tests(test, package, ignored) {
+            Ok(names) => names,
+            Err(e) => {
+                println!("Failed to list the tests in {}: {}", test.display(), e);
+                return (result, false);
+            },
+        };
+        for name in &names {
+            if is_interrupted() {
+                return (result, false);
+            }
+            if config.verbose {
+                println!("Running {}", name);
+            }
+            let exact = Some(ExactTest::new(name, should_panic));
+            match get_coverage(project, package, test, config, ignored, true, exact, Some(&traces)) {
+                Some((res, tp)) => {
+                    result.merge(&res);
+                    test_passed &= tp;
+                },
+                None => test_passed = false,
+            }
+        }
+    }
+    (result, test_passed)
+}
+
+/// The cargo used to run nextest, the one tarpaulin was launched by if any
+fn cargo_command() -> String {
+    env::var("CARGO").unwrap_or_else(|_| "cargo".to_string())
+}
+
+/// Arguments to cargo running a nextest subcommand, the tests are built with
+/// the same features, packages and targets tarpaulin would build
+fn nextest_args(subcommand: &str, features: &[String], config: &Config) -> Vec<String> {
+    let mut args = vec!["nextest".to_string(),
+                        subcommand.to_string(),
+                        "--manifest-path".to_string(),
+                        config.manifest.display().to_string()];
+    if !features.is_empty() {
+        args.push("--features".to_string());
+        args.push(features.join(" "));
+    }
+    if config.all_features {
+        args.push("--all-features".to_string());
+    }
+    if config.no_default_features {
+        args.push("--no-default-features".to_string());
+    }
+    if config.all {
+        args.push("--workspace".to_string());
+    }
+    for package in &config.packages {
+        args.push("--package".to_string());
+        args.push(package.clone());
+    }
+    for package in &config.exclude {
+        args.push("--exclude".to_string());
+        args.push(package.clone());
+    }
+    if config.all_targets {
+        args.push("--all-targets".to_string());
+    }
+    if let Some(ref target) = config.target {
+        args.push("--target".to_string());
+        args.push(target.clone());
+    }
+    for &(set, flag) in &[(config.frozen, "--frozen"), (config.locked, "--locked"), (config.offline, "--offline")] {
+        if set {
+            args.push(flag.to_string());
+        }
+    }
+    args
+}
+
+/// Gets the test executables with tests to run from the JSON output of
+/// `cargo nextest list`, as their path, nextest binary id and target kind.
+/// With a test name only the executables with that test are kept.
+fn parse_nextest_list(output: &str, test_name: Option<&str>) -> Vec<(PathBuf, String, String)> {
+    let list: serde_json::Value = match serde_json::from_str(output) {
+        Ok(list) => list,
+        Err(_) => return vec![],
+    };
+    let suites = match list["rust-suites"].as_object() {
+        Some(suites) => suites,
+        None => return vec![],
+    };
+    suites.values()
+          .filter(|s| {
+              s["testcases"].as_object().map_or(false, |cases| {
+                  test_name.map_or(!cases.is_empty(), |name| cases.contains_key(name))
+              })
+          })
+          .filter_map(|s| {
+              let path = s["binary-path"].as_str()?;
+              let binary_id = s["binary-id"].as_str()?;
+              let kind = s["kind"].as_str().unwrap_or("lib");
+              Some((PathBuf::from(path), binary_id.to_string(), kind.to_string()))
+          })
+          .collect()
+}
+
+/// Builds the tests with cargo nextest and lists the test executables it
+/// would run
+fn list_nextest_binaries(features: &[String], config: &Config) -> io::Result<Vec<(PathBuf, String, String)>> {
+    let output = Command::new(cargo_command())
+        .args(&nextest_args("list", features, config))
+        .args(&["--message-format", "json"])
+        .stderr(Stdio::inherit())
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::new(io::ErrorKind::Other,
+                                  "cargo nextest list failed, check cargo-nextest is installed"));
+    }
+    let test_name = config.test_name.as_ref().map(String::as_str);
+    Ok(parse_nextest_list(&String::from_utf8_lossy(&output.stdout), test_name))
+}
+
+/// Returns the coverage statistics for running the tests of one executable
+/// through `cargo nextest run`. Cargo and nextest are traced without being
+/// instrumented so the test processes nextest spawns can be followed, every
+/// process running the executable gets its breakpoints and their coverage is
+/// merged.
+pub fn get_nextest_coverage(project: &Workspace,
+                            package: &Package,
+                            test: &Path,
+                            binary_id: &str,
+                            features: &[String],
+                            config: &Config) -> Option<(TraceMap, bool)> {
+    let traces = match load_tracemap(project, package, test, true, config) {
+        Ok(traces) => traces,
+        Err(e) => {
+            println!("Error occurred: {}", e);
+            return None;
+        },
+    };
+    let mut filter = format!("binary_id(={})", binary_id);
+    if let Some(ref name) = config.test_name {
+        filter.push_str(&format!(" & test(={})", name));
+    }
+    let test_threads = config.test_threads.unwrap_or(1).to_string();
+    let mut args = nextest_args("run", features, config);
+    args.extend(["--no-fail-fast", "-E", filter.as_str(), "--test-threads", test_threads.as_str()].iter().map(|a| a.to_string()));
+    if config.run_ignored {
+        args.push("--run-ignored".to_string());
+        args.push("all".to_string());
+    }
+    if !config.varargs.is_empty() {
+        args.push("--".to_string());
+        args.extend(config.varargs.iter().cloned());
+    }
+    match fork() {
+        Ok(ForkResult::Parent{ child }) => {
+            match collect_nextest_coverage(test, child, &traces, config) {
+                Ok(t) => Some(t),
+                Err(e) => {
+                    println!("Error occurred: {}", e);
+                    None
+                },
+            }
+        },
+        Ok(ForkResult::Child) => {
+            execute_nextest(&args);
+            None
+        },
+        Err(err) => {
+            println!("Failed to run cargo nextest for {}", test.display());
+            println!("Error {}", err);
+            None
+        },
+    }
+}
+
+/// Returns the coverage statistics for running an example in the given
+/// workspace. Unlike tests, examples aren't passed the test harness arguments.
+pub fn get_example_coverage(project: &Workspace,
+                            package: &Package,
+                            example: &Path,
+                            config: &Config) -> Option<(TraceMap, bool)> {
+    get_coverage(project, package, example, config, false, false, None, None)
+}
+
+/// Returns the coverage statistics for a doctest kept by rustdoc, doctests are
+/// compiled without the libtest harness.
+pub fn get_doctest_coverage(project: &Workspace,
+                            package: &Package,
+                            doctest: &Path,
+                            config: &Config) -> Option<(TraceMap, bool)> {
+    get_coverage(project, package, doctest, config, false, false, None, None)
+}
+
+/// Runs the test and collects its coverage, the tracer data is generated for
+/// the executable unless already generated ones are given
+fn get_coverage(project: &Workspace,
+                package: &Package,
+                test: &Path,
+                config: &Config,
+                ignored: bool,
+                harness: bool,
+                exact: Option<ExactTest>,
+                traces: Option<&TraceMap>) -> Option<(TraceMap, bool)> {
     if !test.exists() {
         return None;
     }
     match fork() {
         Ok(ForkResult::Parent{ child }) => {
-            match collect_coverage(project, test, child, config) {
+            match collect_coverage(project, package, test, child, harness, exact, traces, config) {
                 Ok(t) => {
                     Some(t)
                 },
@@ -292,7 +1767,7 @@ pub fn get_test_coverage(project: &Workspace,
         }
         Ok(ForkResult::Child) => {
             println!("Launching test");
-            execute_test(test, package, ignored, config);
+            execute_test(test, package, ignored, harness, exact.map(|e| e.name), config);
             None
         }
         Err(err) => {
@@ -304,50 +1779,210 @@ pub fn get_test_coverage(project: &Workspace,
 
 }
 
+/// Gets the tracer data of a test executable from the trace plan if one was
+/// given or else its debug info
+fn load_tracemap(project: &Workspace,
+                 package: &Package,
+                 test: &Path,
+                 harness: bool,
+                 config: &Config) -> Result<TraceMap, TracerError> {
+    let name = test.file_name()
+                   .map(|x| x.to_string_lossy().into_owned())
+                   .unwrap_or_default();
+    timings::time(&format!("tracer data {}", name), || {
+        match config.trace_plan {
+            Some(ref plan) => load_trace_plan(plan, &config.manifest),
+            None => generate_tracemap(project, package, test, harness, config),
+        }
+    })
+}
+
 /// Collects the coverage data from the launched test
 fn collect_coverage(project: &Workspace,
+                    package: &Package,
                     test_path: &Path,
                     test: Pid,
-                    config: &Config) -> io::Result<(TraceMap, bool)> {
+                    harness: bool,
+                    exact: Option<ExactTest>,
+                    tracemap: Option<&TraceMap>,
+                    config: &Config) -> Result<(TraceMap, bool), TracerError> {
     let mut test_passed = false;
-    let mut traces = generate_tracemap(project, test_path, config)?;
+    let name = test_path.file_name()
+                        .map(|x| x.to_string_lossy().into_owned())
+                        .unwrap_or_default();
+    let mut traces = match tracemap {
+        Some(t) => t.clone(),
+        None => load_tracemap(project, package, test_path, harness, config)?,
+    };
+    let mut followed_traces = TraceMap::new();
     {
-        let (mut state, mut data) = create_state_machine(test, &mut traces, config);
-        loop {
-            state = state.step(&mut data, config);
-            if state.is_finished() {
-                if let TestState::End(i) = state {
-                    test_passed = i==0;
+        let run_start = Instant::now();
+        let root = normalize_root(project.root());
+        let test_exe = test_path.canonicalize().unwrap_or_else(|_| test_path.to_path_buf());
+        // Tests that re-exec their own binary, such as frameworks running each
+        // test in a subprocess, are always followed
+        let exec_tracer: ExecTracer = Box::new(move |exe: &Path| {
+            if exe == test_exe {
+                match tracemap {
+                    Some(t) => Some(t.clone()),
+                    None => load_tracemap(project, package, exe, harness, config).ok(),
                 }
-                break;
+            } else if config.follow_exec && exe.starts_with(&root) {
+                generate_tracemap(project, package, exe, false, config).ok()
+            } else {
+                None
             }
+        });
+        let (state, mut data) = create_state_machine(test, &mut traces, config, Some(exec_tracer));
+        let state = trace_to_end(state, &mut data, config);
+        if let TestState::End(i) = state {
+            test_passed = i==0;
         }
+        timings::record(&format!("run {}", name), run_start.elapsed());
+        followed_traces = data.take_followed_traces();
         if let Some(m) = data.error_message {
             println!("{}", m);
         }
+        if let Some(sig) = data.abort_signal {
+            // The coverage up to the abort is kept either way
+            test_passed = exact.map_or(false, |e| e.should_panic);
+            match exact {
+                Some(e) if e.should_panic => {
+                    println!("Test {} aborted with {:?} as expected by #[should_panic]", e.name, sig);
+                },
+                Some(e) => println!("Test {} aborted with {:?}", e.name, sig),
+                None => {
+                    println!("Test aborted with {:?}, with panic=abort use --runner isolated so \
+                              each #[should_panic] test is checked on its own", sig);
+                },
+            }
+        }
         if state == TestState::Abort  {
             println!("Can't collect coverage data. Exiting");
             std::process::exit(1);
         }
+        match data.exited_before_exec {
+            Some(TRACE_FAILED) => return Err(TracerError::PtraceDenied),
+            Some(_) => return Err(TracerError::ExecFailed(test_path.to_path_buf())),
+            None => {},
+        }
+    }
+    if config.audit {
+        if let Err(e) = audit_entry_points(test_path, &traces, config) {
+            println!("Failed to audit {}: {}", test_path.display(), e);
+        }
+    }
+    traces.merge(&followed_traces);
+    Ok((apply_granularity(test_path, traces, config)?, test_passed))
+}
+
+/// Steps the state machine until the traced process finishes or tarpaulin is
+/// interrupted, returning the last state
+fn trace_to_end(mut state: TestState, data: &mut LinuxData, config: &Config) -> TestState {
+    loop {
+        if is_interrupted() {
+            // Keep what's been collected, the test won't be continued
+            data.kill_all();
+            return state;
+        }
+        state = state.step(data, config);
+        if state.is_finished() {
+            return state;
+        }
+    }
+}
+
+/// Turns the traces of a test executable into function coverage or branches
+/// told apart by discriminators if either was asked for
+fn apply_granularity(test_path: &Path, traces: TraceMap, config: &Config) -> Result<TraceMap, TracerError> {
+    if config.granularity == Granularity::Function {
+        function_coverage(test_path, &traces, config)
+    } else if config.branch_coverage {
+        discriminator_branches(test_path, &traces)
+    } else {
+        Ok(traces)
+    }
+}
+
+/// Collects the coverage of the processes running the test executable that
+/// are spawned by the traced cargo nextest
+fn collect_nextest_coverage(test_path: &Path,
+                            cargo: Pid,
+                            tracemap: &TraceMap,
+                            config: &Config) -> Result<(TraceMap, bool), TracerError> {
+    let name = test_path.file_name()
+                        .map(|x| x.to_string_lossy().into_owned())
+                        .unwrap_or_default();
+    // Cargo and nextest have no breakpoints of their own
+    let mut launcher_traces = TraceMap::new();
+    let test_exe = test_path.canonicalize().unwrap_or_else(|_| test_path.to_path_buf());
+    let exec_tracer: ExecTracer = Box::new(move |exe: &Path| {
+        if exe == test_exe {
+            Some(tracemap.clone())
+        } else {
+            None
+        }
+    });
+    let run_start = Instant::now();
+    let (state, mut data) = create_state_machine(cargo, &mut launcher_traces, config, Some(exec_tracer));
+    // Cargo execs nextest which spawns the tests, and nextest waits on its
+    // test processes so it needs the signals it's sent
+    data.follow_untraced = true;
+    data.forward_signals = true;
+    let state = trace_to_end(state, &mut data, config);
+    timings::record(&format!("run {}", name), run_start.elapsed());
+    if let Some(ref m) = data.error_message {
+        println!("{}", m);
+    }
+    if state == TestState::Abort  {
+        println!("Can't collect coverage data. Exiting");
+        std::process::exit(1);
     }
-    Ok((traces, test_passed))
+    match data.exited_before_exec {
+        Some(TRACE_FAILED) => return Err(TracerError::PtraceDenied),
+        Some(_) => return Err(TracerError::ExecFailed(PathBuf::from(cargo_command()))),
+        None => {},
+    }
+    let traces = data.take_followed_traces();
+    Ok((apply_granularity(test_path, traces, config)?, state == TestState::End(0)))
 }
 
-/// Launches the test executable
-fn execute_test(test: &Path, package: &Package, ignored: bool, config: &Config) {
+/// Gets the libtest names of the tests marked `#[should_panic]` in the
+/// workspace
+fn should_panic_tests(project: &Workspace, config: &Config) -> HashSet<String> {
+    get_line_analysis(project, config).values()
+                                      .flat_map(|a| a.should_panic.iter().cloned())
+                                      .collect()
+}
+
+/// Launches the test executable, given an exact test name only that test is
+/// ran
+fn execute_test(test: &Path,
+                package: &Package,
+                ignored: bool,
+                harness: bool,
+                exact: Option<&str>,
+                config: &Config) {
     let exec_path = CString::new(test.to_str().unwrap()).unwrap();
     match personality::disable_aslr() {
         Ok(_) => {},
         Err(e) => println!("ASLR disable failed: {}", e),
     }
-    request_trace().expect("Failed to trace");
+    if let Err(e) = request_trace() {
+        eprintln!("Failed to trace {}: {}", test.display(), e);
+        std::process::exit(TRACE_FAILED);
+    }
     println!("running {}", test.display());
     if let Some(parent) = package.manifest_path().parent() {
         let _ = env::set_current_dir(parent);
     }
 
-    let mut envars: Vec<CString> = vec![CString::new("RUST_TEST_THREADS=1").unwrap()];
-    for (key, value) in env::vars() {
+    // The tracer assumes the tests run on one thread unless asked otherwise
+    let test_threads = config.test_threads
+        .or_else(|| env::var("RUST_TEST_THREADS").ok().and_then(|x| x.parse().ok()))
+        .unwrap_or(1);
+    let mut envars = vec![CString::new(format!("RUST_TEST_THREADS={}", test_threads)).unwrap()];
+    for (key, value) in env::vars().filter(|&(ref k, _)| k != "RUST_TEST_THREADS") {
         let mut temp = String::new();
         temp.push_str(key.as_str());
         temp.push('=');
@@ -359,15 +1994,95 @@ fn execute_test(test: &Path, package: &Package, ignored: bool, config: &Config)
     } else {
         vec![exec_path.clone()]
     };
+    if let Some(name) = exact {
+        argv.push(CString::new(name).unwrap_or_default());
+        argv.push(CString::new("--exact").unwrap());
+    }
     if config.verbose {
         envars.push(CString::new("RUST_BACKTRACE=1").unwrap());
-    } else {
+    } else if harness {
         argv.push(CString::new("--quiet").unwrap());
     }
     for s in &config.varargs {
         argv.push(CString::new(s.as_bytes()).unwrap_or_default());
     }
-    execve(&exec_path, &argv, envars.as_slice())
-        .unwrap();
+    if let Err(e) = execve(&exec_path, &argv, envars.as_slice()) {
+        eprintln!("Failed to execute {}: {}", test.display(), e);
+        std::process::exit(EXEC_FAILED);
+    }
+}
+
+
+/// Launches cargo nextest with the given arguments. ASLR stays disabled for
+/// the processes it spawns so the test executables load where expected.
+fn execute_nextest(args: &[String]) {
+    match personality::disable_aslr() {
+        Ok(_) => {},
+        Err(e) => println!("ASLR disable failed: {}", e),
+    }
+    if let Err(e) = request_trace() {
+        eprintln!("Failed to trace cargo nextest: {}", e);
+        std::process::exit(TRACE_FAILED);
+    }
+    let cargo = CString::new(cargo_command()).unwrap_or_default();
+    let mut argv = vec![cargo.clone()];
+    argv.extend(args.iter().map(|a| CString::new(a.as_bytes()).unwrap_or_default()));
+    if let Err(e) = execvp(&cargo, &argv) {
+        eprintln!("Failed to execute cargo nextest: {}", e);
+        std::process::exit(EXEC_FAILED);
+    }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_list() {
+        let output = "tests::add: test\n\
+                      tests::nested::sub: test\n\
+                      benches::sum: bench\n\
+                      \n\
+                      2 tests, 1 benchmark\n";
+        assert_eq!(parse_test_list(output), vec!["tests::add", "tests::nested::sub"]);
+        assert!(parse_test_list("").is_empty());
+    }
+
+    #[test]
+    fn nextest_list() {
+        let output = r#"{
+            "test-count": 3,
+            "rust-suites": {
+                "my-crate": {
+                    "binary-id": "my-crate",
+                    "binary-path": "/p/target/debug/deps/my_crate-0123",
+                    "kind": "lib",
+                    "testcases": { "tests::add": { "ignored": false } }
+                },
+                "my-crate::api": {
+                    "binary-id": "my-crate::api",
+                    "binary-path": "/p/target/debug/deps/api-4567",
+                    "kind": "test",
+                    "testcases": { "get": { "ignored": false }, "put": { "ignored": true } }
+                },
+                "my-crate::bin/tool": {
+                    "binary-id": "my-crate::bin/tool",
+                    "binary-path": "/p/target/debug/deps/tool-89ab",
+                    "kind": "bin",
+                    "testcases": {}
+                }
+            }
+        }"#;
+        let mut binaries = parse_nextest_list(output, None);
+        binaries.sort();
+        assert_eq!(binaries, vec![
+            (PathBuf::from("/p/target/debug/deps/api-4567"), "my-crate::api".to_string(), "test".to_string()),
+            (PathBuf::from("/p/target/debug/deps/my_crate-0123"), "my-crate".to_string(), "lib".to_string()),
+        ]);
+        let named = parse_nextest_list(output, Some("put"));
+        assert_eq!(named.len(), 1);
+        assert_eq!(named[0].1, "my-crate::api");
+        assert!(parse_nextest_list("not json", None).is_empty());
+    }
+}
@@ -5,12 +5,14 @@ extern crate object;
 extern crate memmap;
 extern crate fallible_iterator;
 extern crate rustc_demangle;
+extern crate env_logger;
 #[macro_use]
 extern crate clap;
 
+use std::env;
 use std::path::Path;
 use clap::{App, Arg, SubCommand, ArgSettings};
-use cargo_tarpaulin::{run, RunError};
+use cargo_tarpaulin::{install_interrupt_handler, run, RunError};
 use cargo_tarpaulin::config::*;
 
 
@@ -38,29 +40,107 @@ fn main() -> Result<(), RunError> {
             .about("Tool to analyse test coverage of cargo projects")
             .version(concat!("version: ", crate_version!()))
             .args_from_usage(
-                 "--verbose -v 'Show extra output'
+                 "--verbose -v 'Show extra output, including the debug log of the tracer unless RUST_LOG is set'
+                 --quiet -q 'Hide the compile and tracing progress indicators and the coverage summary unless --out Stdout is given'
+                 --explain 'Prints the address, function, compilation unit and classification of every traced line'
+                 --dump-traces [FILE] 'Writes the addresses and classification of every traced line before and after deduplication to a JSON file for bug reports'
+                 --audit 'Warns about function entry addresses that never trapped although the function ran'
                  --ignore-tests 'ignore lines of test functions when collecting coverage'
+                 --count-test-lines 'Count the lines of tests even with --ignore-tests and summarise their coverage separately'
                  --ignore-panics 'ignore panic macros in tests'
+                 --include-panics 'Count functions whose body is only a panic, such as unimplemented!(), as coverable'
                  --count   'Counts the number of hits during coverage'
+                 --watch 'Reruns coverage when a source file changes, checking for changes once a second. Use with --skip-clean to avoid full rebuilds'
+                 --timings 'Prints how long compiling, tracing, merging and exporting took'
                  --ignored -i 'Run ignored tests as well'
+                 --test-name [NAME] 'Only run the test with this exact name, such as tests::login_flow, reporting the code it ran'
                  --line -l    'Line coverage'
                  --skip-clean 'Skips the clean stage to reduce build times, may affect coverage results'
-                 --branch -b  'Branch coverage: NOT IMPLEMENTED'
+                 --reuse-build 'Traces the test executables from a previous build in the target directory without compiling'
+                 --cfg-tarpaulin 'Build with --cfg tarpaulin, #[cfg_attr(tarpaulin, skip)] then needs the nightly custom_attribute feature'
+                 --branch -b  'Branch coverage, off by default. A line with two blocks such as a one line if else reports whether each ran'
+                 --follow-exec 'Follow executables in the project launched by the tests and include their coverage'
                  --forward -f 'Forwards unexpected signals to test. Tarpaulin will still take signals it is expecting.'
                  --coveralls [KEY]  'Coveralls key, either the repo token, or if you're using travis use $TRAVIS_JOB_ID and specify travis-{ci|pro} in --ciserver'
+                 --coveralls-token-file [FILE] 'File containing the coveralls key, used if --coveralls is not given. Otherwise COVERALLS_REPO_TOKEN is used if set'
+                 --source-root [DIR] 'Directory paths sent to coveralls are relative to, defaults to the git repository root'
+                 --upload-coveralls [FILE] 'Uploads a coveralls.json written by --out Coveralls instead of running the tests'
+                 --flag [NAME] 'Flag naming the component the coverage is of in the coveralls and codecov reports'
                  --report-uri [URI] 'URI to send report to, only used if the option --coveralls is used'
+                 --locked 'Require Cargo.lock is up to date'
+                 --frozen 'Require Cargo.lock and cache are up to date'
+                 --offline 'Run without accessing the network'
                  --no-default-features 'Do not include default features'
                  --features [FEATURE]... 'Features to be included in the target project'
                  --all-features 'Build all available features'
                  --all        'Build all packages in the workspace'
+                 --binary [PATH] 'Traces the prebuilt test binary instead of building the project'
+                 --post-build-script [CMD] 'Shell command to run from the project root after the tests are built, failing if it fails'
+                 --pre-test-script [CMD] 'Shell command to run from the project root before the tests are traced, failing if it fails'
+                 --attach [PID] 'Traces the running test process until it exits, it must have been built with the tarpaulin flags'
+                 --trace-plan [FILE] 'JSON list of path, line and address objects to trace instead of reading the debug info'
+                 --example [NAME] 'Run the named example instead of the tests and report its coverage'
+                 --doc 'Also trace the doctests, keeping them for tracing needs a nightly toolchain'
+                 --all-targets 'Test all targets: lib, bins, tests, benches and examples'
                  --packages -p [PACKAGE]... 'Package id specifications for which package should be build. See cargo help pkgid for more info'
                  --exclude -e [PACKAGE]... 'Package id specifications to exclude from coverage. See cargo help pkgid for more info'
-                 --exclude-files [FILE]... 'Exclude given files from coverage results has * wildcard'
+                 --exclude-dependencies 'Only cover the sources of the package under test, excluding path dependencies in the workspace'
+                 --include-out-dir 'Include code generated by build scripts in OUT_DIR in the coverage results'
+                 --default-excludes 'If no files are excluded exclude build scripts and the target directory'
+                 --changed-only 'Only report coverage for files changed according to git diff'
+                 --base [REV] 'Revision --changed-only compares against (default HEAD)'
+                 --exclude-files [FILE]... 'Exclude given files from coverage results has * wildcard, matching anywhere in the path from the project root or the file name'
+                 --exclude-line-pattern [REGEX]... 'Exclude source lines matching the regex from coverage results'
+                 --emit-socket [ADDR] 'Streams line hits as JSON to the TCP address, or UDP if prefixed with udp://'
+                 --report-name [NAME] 'Prefix of the report file names, so the reports of different runs can be written to one directory'
+                 --save-report [PATH] 'Saves a JSON report of the results for a later run to --compare against'
+                 --compare [PATH] 'Prints the change in coverage since the report saved at the path'
+                 --fail-under [PERCENT] 'Fails if the overall coverage is below the given percentage'
+                 --ratchet [FILE] 'Fails if coverage is below that of the JSON report'
+                 --update-baseline 'Updates the --ratchet report when coverage increases or creates it if missing'
+                 --no-regressions [FILE] 'Fails listing the lines covered in the JSON report which are no longer covered'
+                 --embed-source 'Include the source text of each uncovered region in the JSON report'
+                 --precision [DIGITS] 'Number of decimal places in the printed percentages (default 1)'
+                 --minimal-report 'Only prints the overall coverage, skipping the per file results and report files'
+                 --fail-under-file [PERCENT] 'Fails if the coverage of any file is below the given percentage'
+                 --file-threshold-exclude [FILE]... 'Files exempt from --fail-under-file has * wildcard'
+                 --post-test-delay [MS] 'Keep collecting coverage from processes forked by a test for up to this long after it exits'
+                 --test-threads [N] 'Number of threads to run the tests with, defaults to RUST_TEST_THREADS or 1'
+                 --target [TRIPLE] 'Build the tests for the target triple, such as x86_64-unknown-linux-musl'
+                 --jobs -j [N] 'Number of parallel jobs to compile with, defaults to the number of CPUs'
+                 --test-namespace [SUBSTR]... 'Functions with demangled names containing this are tests (default tests::)'
+                 --generated-main-symbol [SUBSTR] 'Demangled name of the main generated by the test harness (default __test::main)'
                  --timeout -t [SECONDS] 'Integer for the maximum time in seconds without response from test before timeout (default is 1 minute).'")
             .args(&[
                 Arg::from_usage("--out -o [FMT]   'Output format of coverage report'")
                     .possible_values(&OutputFile::variants())
                     .multiple(true),
+                Arg::from_usage("--feature-matrix [FEATURES]... 'Sets of features to build and run the tests with in turn, merging the coverage'")
+                    .use_delimiter(false),
+                Arg::from_usage("--compress [FMT] 'Compress the report files, appending the extension to their names'")
+                    .possible_values(&Compression::variants())
+                    .case_insensitive(true),
+                Arg::from_usage("--breakdown [KIND] 'Also report unit and/or integration test coverage separately'")
+                    .possible_values(&Breakdown::variants())
+                    .case_insensitive(true),
+                Arg::from_usage("--macro-coverage [SITE] 'Attribute lines in macro_rules! expansions to the macro definition or invocation (default), def needs a nightly toolchain'")
+                    .possible_values(&MacroCoverage::variants())
+                    .case_insensitive(true),
+                Arg::from_usage("--print-summary [FMT] 'Format of the coverage summary, machine adds a line of the form coverage: <covered>/<total> (<percent>%)'")
+                    .possible_values(&SummaryFormat::variants())
+                    .case_insensitive(true),
+                Arg::from_usage("--granularity [LEVEL] 'Report coverage per line (default), per instruction address or the functions executed'")
+                    .possible_values(&Granularity::variants())
+                    .case_insensitive(true),
+                Arg::from_usage("--group-by [GROUP] 'List coverage by file (default) or by the module path of the functions'")
+                    .possible_values(&GroupBy::variants())
+                    .case_insensitive(true),
+                Arg::from_usage("--path-style [STYLE] 'Write report paths as absolute or relative to the workspace (default) or git repository root, coveralls defaults to git-relative'")
+                    .possible_values(&PathStyle::variants())
+                    .case_insensitive(true),
+                Arg::from_usage("--runner [RUNNER] 'Run all the tests of an executable in one process (default), each test in its own process (isolated) or through cargo nextest (nextest)'")
+                    .possible_values(&Runner::variants())
+                    .case_insensitive(true),
                 Arg::from_usage("--root -r [DIR]  'Root directory containing Cargo.toml to use'")
                     .validator(is_dir),
                 Arg::from_usage("--ciserver [SERVICE] 'CI server being used'")
@@ -73,7 +153,26 @@ fn main() -> Result<(), RunError> {
         .get_matches();
 
     let args = args.subcommand_matches("tarpaulin").unwrap_or(&args);
+    // RUST_LOG takes precedence, otherwise --verbose shows the debug messages
+    let filter = if args.is_present("verbose") {
+        "tarpaulin=debug"
+    } else {
+        "tarpaulin=warn"
+    };
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(filter)).init();
+    // The working directory may have been deleted from under us, such as by
+    // a CI cleanup step
+    if let Err(e) = env::current_dir() {
+        if !args.value_of("root").map_or(false, |r| Path::new(r).is_absolute()) {
+            println!("Failed to get the current directory: {}", e);
+            println!("Run tarpaulin from the project directory or give its absolute path with --root");
+            return Err(RunError::Manifest);
+        }
+    }
     let config = Config::from(args);
+    if let Err(e) = install_interrupt_handler() {
+        println!("Failed to install interrupt handler: {}", e);
+    }
     let res = run(&config);
     println!("Tarpaulin finished");
     res
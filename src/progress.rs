@@ -0,0 +1,65 @@
+use config::Config;
+use indicatif::{ProgressBar, ProgressStyle};
+use nix::unistd::isatty;
+use nix::libc::STDOUT_FILENO;
+
+
+/// Progress indicator shown during the long running compile and trace phases.
+/// Nothing is drawn if stdout isn't a terminal or the user asked for quiet
+/// output.
+pub struct Progress {
+    bar: Option<ProgressBar>,
+}
+
+impl Progress {
+    /// Creates a spinner displaying the message for a phase of unknown length
+    pub fn spinner(config: &Config, message: &str) -> Progress {
+        let bar = if is_enabled(config) {
+            let bar = ProgressBar::new_spinner();
+            bar.set_message(message);
+            bar.enable_steady_tick(100);
+            Some(bar)
+        } else {
+            None
+        };
+        Progress { bar }
+    }
+
+    /// Creates a bar for a phase of `len` steps
+    pub fn bar(config: &Config, len: u64) -> Progress {
+        let bar = if is_enabled(config) {
+            let bar = ProgressBar::new(len);
+            bar.set_style(ProgressStyle::default_bar()
+                                        .template("[{bar:40}] {pos}/{len} {msg}"));
+            Some(bar)
+        } else {
+            None
+        };
+        Progress { bar }
+    }
+
+    pub fn set_message(&self, message: &str) {
+        if let Some(ref bar) = self.bar {
+            bar.set_message(message);
+        }
+    }
+
+    /// Advances the bar by one step
+    pub fn inc(&self) {
+        if let Some(ref bar) = self.bar {
+            bar.inc(1);
+        }
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        if let Some(ref bar) = self.bar {
+            bar.finish_and_clear();
+        }
+    }
+}
+
+fn is_enabled(config: &Config) -> bool {
+    !config.quiet && isatty(STDOUT_FILENO).unwrap_or(false)
+}
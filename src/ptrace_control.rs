@@ -1,6 +1,8 @@
 use std::ptr;
+use std::fs::read_dir;
 use nix::sys::signal::Signal;
 use nix::sys::ptrace::*;
+use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
 use nix::libc::{c_void, c_long};
 use nix::unistd::Pid;
 use nix::{Error, Result};
@@ -64,6 +66,59 @@ pub fn request_trace() -> Result<()> {
     traceme()
 }
 
+/// Gets the threads of a process from `/proc/<pid>/task`
+pub fn process_threads(pid: Pid) -> Vec<Pid> {
+    read_dir(format!("/proc/{}/task", pid))
+        .map(|tasks| {
+            tasks.filter_map(|t| t.ok())
+                 .filter_map(|t| t.file_name().to_str().and_then(|s| s.parse().ok()))
+                 .map(Pid::from_raw)
+                 .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Attaches to every thread of a running process. Each thread is waited on
+/// until it stops and has the trace options set, so none of them can run into
+/// a breakpoint untraced. Returns the threads attached to.
+pub fn attach_to(pid: Pid) -> Result<Vec<Pid>> {
+    let mut attached: Vec<Pid> = vec![];
+    // Threads started while attaching are picked up by checking again
+    loop {
+        let new = process_threads(pid).into_iter()
+                                      .filter(|t| !attached.contains(t))
+                                      .collect::<Vec<_>>();
+        if new.is_empty() {
+            break;
+        }
+        for tid in new {
+            match attach(tid) {
+                Ok(()) => {},
+                // The thread exited since the task list was read
+                Err(Error::Sys(Errno::ESRCH)) if tid != pid => continue,
+                Err(e) => return Err(e),
+            }
+            let stopped = loop {
+                match waitpid(tid, Some(WaitPidFlag::__WALL))? {
+                    WaitStatus::Stopped(_, Signal::SIGSTOP) => break true,
+                    // Other signals are delivered once the thread continues
+                    WaitStatus::Stopped(_, sig) => cont(tid, Some(sig))?,
+                    _ => break false,
+                }
+            };
+            if !stopped {
+                if tid == pid {
+                    return Err(Error::Sys(Errno::ESRCH));
+                }
+                continue;
+            }
+            trace_children(tid)?;
+            attached.push(tid);
+        }
+    }
+    Ok(attached)
+}
+
 pub fn get_event_data(pid: Pid) -> Result<c_long> {
     getevent(pid)
 }
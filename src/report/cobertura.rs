@@ -1,5 +1,4 @@
 use std::time::{SystemTime, UNIX_EPOCH};
-use std::fs::File;
 use std::path::Path;
 use std::io::prelude::*;
 use std::io::Cursor;
@@ -8,16 +7,19 @@ use quick_xml::Writer;
 use quick_xml::events::{Event, BytesEnd, BytesStart, BytesDecl};
 use quick_xml::Result;
 use traces::{TraceMap, CoverageStat};
-use config::Config;
+use config::{Config, PathStyle};
+use report::create_output;
 
 
 
-fn write_header<T:Write>(writer: &mut Writer<T>, config: &Config) -> Result<usize> {
+/// The class file names are relative to the source, which is empty when
+/// they're absolute
+fn write_header<T:Write>(writer: &mut Writer<T>, root: Option<&Path>) -> Result<usize> {
 
     writer.write_event(Event::Start(BytesStart::borrowed(b"sources", b"sources".len())))?;
     writer.write_event(Event::Start(BytesStart::borrowed(b"source", b"source".len())))?;
 
-    let parent_folder = match config.manifest.parent() {
+    let parent_folder = match root {
         Some(s) => s.to_str().unwrap_or_default(),
         None => "",
     };
@@ -29,17 +31,14 @@ fn write_header<T:Write>(writer: &mut Writer<T>, config: &Config) -> Result<usiz
 
 /// Input only from single source file
 fn write_class<T:Write>(writer: &mut Writer<T>,
-                        manifest_path: &Path,
+                        root: Option<&Path>,
                         filename: &Path,
-                        coverage: &TraceMap) ->Result<usize> {
+                        coverage: &TraceMap,
+                        config: &Config) ->Result<usize> {
     if !coverage.is_empty() {
-        let covered = coverage.covered_in_path(filename);
-        let covered = (covered as f32)/(coverage.coverable_in_path(filename) as f32);
+        let covered = coverage.coverage_percentage_in_path(filename);
 
-        let tidy_filename = match filename.strip_prefix(manifest_path) {
-            Ok(p) => p,
-            _ => filename,
-        };
+        let tidy_filename = config.report_path(filename, root);
         let name = filename.file_stem().unwrap_or_default().to_str().unwrap_or_default();
 
         let mut class = BytesStart::owned(b"class".to_vec(), b"class".len());
@@ -74,11 +73,11 @@ fn write_class<T:Write>(writer: &mut Writer<T>,
 /// Input only tracer data from a single source folder
 fn write_package<T:Write>(mut writer: &mut Writer<T>,
                           package: &Path,
-                          manifest_path: &Path,
+                          root: Option<&Path>,
                           package_name: &str,
-                          coverage: &TraceMap) -> Result<usize> {
-    let covered = coverage.covered_in_path(package);
-    let covered = (covered as f32)/(coverage.coverable_in_path(package) as f32);
+                          coverage: &TraceMap,
+                          config: &Config) -> Result<usize> {
+    let covered = coverage.coverage_percentage_in_path(package);
 
     let mut pack = BytesStart::owned(b"package".to_vec(), b"package".len());
     pack.push_attribute(("name", package_name));
@@ -90,7 +89,7 @@ fn write_package<T:Write>(mut writer: &mut Writer<T>,
 
     for file in &coverage.files() {
         if file.parent() == Some(package) {
-            write_class(&mut writer, manifest_path, file, coverage)?;
+            write_class(&mut writer, root, file, coverage, config)?;
         }
     }
 
@@ -99,7 +98,7 @@ fn write_package<T:Write>(mut writer: &mut Writer<T>,
 }
 
 pub fn export(coverage_data: &TraceMap, config: &Config) {
-    let mut file = File::create("cobertura.xml").unwrap();
+    let mut file = create_output("cobertura.xml", config).unwrap();
     let mut writer = Writer::new(Cursor::new(Vec::new()));
     writer.write_event(Event::Decl(BytesDecl::new(b"1.0", None, None))).unwrap();
     // Construct cobertura xml
@@ -116,7 +115,9 @@ pub fn export(coverage_data: &TraceMap, config: &Config) {
     }
 
     writer.write_event(Event::Start(cov)).unwrap();
-    let _ = write_header(&mut writer, &config);
+    let root = config.report_root(PathStyle::WorkspaceRelative);
+    let root = root.as_ref().map(|r| r.as_path());
+    let _ = write_header(&mut writer, root);
     // other data
     writer.write_event(Event::Start(BytesStart::borrowed(b"packages", b"packages".len()))).unwrap();
 
@@ -134,7 +135,7 @@ pub fn export(coverage_data: &TraceMap, config: &Config) {
                 _ => manifest_path,
             };
             let package_name = package_name.to_str().unwrap_or_default();
-            let _ = write_package(&mut writer, &parent, &manifest_path, package_name, &coverage_data);
+            let _ = write_package(&mut writer, &parent, root, package_name, &coverage_data, config);
         }
     }
 
@@ -0,0 +1,82 @@
+use std::collections::BTreeMap;
+use serde_json::{self, Value};
+use traces::{TraceMap, CoverageStat};
+use config::{Config, PathStyle};
+use report::create_output;
+
+
+/// The coverage of a line in Codecov's format, hits for lines and the taken
+/// out of the possible outcomes for branches and conditions
+fn line_coverage(stats: &CoverageStat) -> Value {
+    match *stats {
+        CoverageStat::Line(hits) => json!(hits),
+        CoverageStat::Branch(ref l) => {
+            json!(format!("{}/2", (l.been_true as u64) + (l.been_false as u64)))
+        },
+        CoverageStat::Condition(ref c) => {
+            let taken = c.iter().map(|l| (l.been_true as usize) + (l.been_false as usize)).sum::<usize>();
+            json!(format!("{}/{}", taken, c.len() * 2))
+        },
+    }
+}
+
+/// Builds the report in Codecov's JSON coverage format, with the flag given to
+/// `--flag` so the reports of each component of a monorepo are kept apart.
+fn to_json(coverage_data: &TraceMap, config: &Config) -> Value {
+    // Like coveralls codecov matches files against the repository
+    let root = config.report_root(PathStyle::GitRelative);
+    let mut files = BTreeMap::new();
+    for (file, traces) in coverage_data.iter() {
+        let path = config.report_path(file, root.as_ref().map(|r| r.as_path()));
+        let lines = traces.iter()
+                          .map(|t| (t.line.to_string(), line_coverage(&t.stats)))
+                          .collect::<BTreeMap<_, _>>();
+        files.insert(path.display().to_string(), lines);
+    }
+    let mut report = json!({ "coverage": files });
+    if let Some(ref flag) = config.flag {
+        report["flags"] = json!([flag]);
+    }
+    report
+}
+
+/// Writes the coverage results to `codecov.json` to be uploaded by the codecov
+/// uploader
+pub fn export(coverage_data: &TraceMap, config: &Config) {
+    let mut file = create_output("codecov.json", config).unwrap();
+    serde_json::to_writer(&mut file, &to_json(coverage_data, config)).unwrap();
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use traces::{Trace, LogicState};
+
+    #[test]
+    fn flagged_report() {
+        let mut coverage = TraceMap::new();
+        coverage.add_trace(Path::new("/p/src/lib.rs"), Trace {
+            line: 2,
+            address: None,
+            length: 1,
+            stats: CoverageStat::Line(3),
+        });
+        coverage.add_trace(Path::new("/p/src/lib.rs"), Trace {
+            line: 4,
+            address: None,
+            length: 1,
+            stats: CoverageStat::Branch(LogicState { been_true: true, been_false: false }),
+        });
+        let mut config = Config::default();
+        config.manifest = Path::new("/p/Cargo.toml").to_path_buf();
+        config.path_style = Some(PathStyle::WorkspaceRelative);
+        let report = to_json(&coverage, &config);
+        assert_eq!(report["coverage"]["src/lib.rs"]["2"], json!(3));
+        assert_eq!(report["coverage"]["src/lib.rs"]["4"], json!("1/2"));
+        assert!(report.get("flags").is_none());
+        config.flag = Some("backend".to_string());
+        assert_eq!(to_json(&coverage, &config)["flags"], json!(["backend"]));
+    }
+}
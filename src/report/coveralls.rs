@@ -1,56 +1,232 @@
 use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
 use coveralls_api::*;
+use curl::easy::{Easy, Form};
+use serde_json::{self, Value};
 use traces::{TraceMap, CoverageStat};
-use config::Config;
+use config::{Config, PathStyle};
+use report::create_output;
 
-pub fn export(coverage_data: &TraceMap, config: &Config) {
-    if let Some(ref key) = config.coveralls {
-        let id = match config.ci_tool {
-            Some(ref service) => Identity::ServiceToken(Service {
-                service_name: service.clone(),
-                service_job_id: key.clone()
-            }),
-            _ => Identity::RepoToken(key.clone()),
-        };
-        let mut report = CoverallsReport::new(id);
-        for file in &coverage_data.files() {
-            let rel_path = config.strip_project_path(file);
-            let mut lines: HashMap<usize, usize> = HashMap::new();
-            let fcov = coverage_data.get_child_traces(file);
-
-            for c in &fcov {
-                match c.stats {
-                    CoverageStat::Line(hits) => {
-                        lines.insert(c.line as usize, hits as usize);
-                    },
-                    _ => {
-                        println!("Support for coverage statistic not implemented or supported for coveralls.io");
-                    },
-                }
+/// Endpoint reports are uploaded to unless a report URI is given
+const COVERALLS_URI: &str = "https://coveralls.io/api/v1/jobs";
+
+/// Checks the coveralls key looks valid so a malformed one fails before the
+/// tests are run rather than at upload. Travis job ids are numeric and repo
+/// tokens are alphanumeric, the job ids of other services vary.
+pub fn validate_key(config: &Config) -> Result<(), String> {
+    let key = match config.coveralls {
+        Some(ref key) => key.trim(),
+        None => return Ok(()),
+    };
+    if key.is_empty() {
+        return Err("the key is empty".to_string());
+    }
+    match config.ci_tool {
+        Some(CiService::Travis) | Some(CiService::TravisPro) => {
+            if !key.chars().all(|c| c.is_ascii_digit()) {
+                return Err("travis job ids should be a number, use $TRAVIS_JOB_ID".to_string());
             }
-            if let Ok(source) = Source::new(&rel_path, file, &lines, &None, false) {
-                report.add_source(source);
+        },
+        Some(_) => {},
+        None => {
+            if !key.chars().all(|c| c.is_ascii_alphanumeric()) {
+                return Err("repo tokens should only contain letters and numbers".to_string());
             }
-        }
+        },
+    }
+    Ok(())
+}
 
-        let res = match config.report_uri {
-            Some(ref uri) => {
-                println!("Sending report to endpoint: {}", uri);
-                report.send_to_endpoint(uri)
-            },
-            None => {
-                println!("Sending coverage data to coveralls.io");
-                report.send_to_coveralls()
-            }
-        };
+/// Builds the coveralls report, without a key the report has an empty repo
+/// token so it can still be written out and inspected
+fn build_report(coverage_data: &TraceMap, config: &Config) -> CoverallsReport {
+    let key = config.coveralls.clone().unwrap_or_default();
+    let id = match config.ci_tool {
+        Some(ref service) => Identity::ServiceToken(Service {
+            service_name: service.clone(),
+            service_job_id: key
+        }),
+        _ => Identity::RepoToken(key),
+    };
+    let mut report = CoverallsReport::new(id);
+    // Coveralls matches files against the repository so by default paths are
+    // relative to its root, which in a monorepo may be above the project.
+    let root = config.report_root(PathStyle::GitRelative);
+    for file in &coverage_data.files() {
+        let rel_path = config.report_path(file, root.as_ref().map(|r| r.as_path()));
+        let mut lines: HashMap<usize, usize> = HashMap::new();
+        let fcov = coverage_data.get_child_traces(file);
 
-        if config.verbose {
-            match res {
-                Ok(_) => {},
-                Err(e) => println!("Coveralls send failed. {}", e),
+        for c in &fcov {
+            match c.stats {
+                CoverageStat::Line(hits) => {
+                    lines.insert(c.line as usize, hits as usize);
+                },
+                _ => {
+                    println!("Support for coverage statistic not implemented or supported for coveralls.io");
+                },
             }
         }
-    } else {
+        if let Ok(source) = Source::new(&rel_path, file, &lines, &None, false) {
+            report.add_source(source);
+        }
+    }
+    report
+}
+
+/// Serializes the report adding the flag naming the job, so separate runs for
+/// each component can be told apart
+fn to_json(report: &CoverallsReport, config: &Config) -> Result<Value, String> {
+    let mut value = serde_json::to_value(report).map_err(|e| e.to_string())?;
+    if let (Some(flag), Some(fields)) = (config.flag.as_ref(), value.as_object_mut()) {
+        fields.insert("flag_name".to_string(), Value::String(flag.clone()));
+    }
+    Ok(value)
+}
+
+/// The report written to `coveralls.json`, the repo token is secret so it's
+/// left out and given again to `--upload-coveralls`
+fn exported_json(coverage_data: &TraceMap, config: &Config) -> Result<Value, String> {
+    let mut report = to_json(&build_report(coverage_data, config), config)?;
+    if let Some(fields) = report.as_object_mut() {
+        fields.remove("repo_token");
+    }
+    Ok(report)
+}
+
+/// Writes the report coveralls would be sent to `coveralls.json` without
+/// uploading it, so it can be inspected or uploaded later with
+/// `--upload-coveralls`
+pub fn export(coverage_data: &TraceMap, config: &Config) {
+    let report = exported_json(coverage_data, config).unwrap();
+    let mut file = create_output("coveralls.json", config).unwrap();
+    serde_json::to_writer(&mut file, &report).unwrap();
+}
+
+/// Sends the coverage results to coveralls or the report URI
+pub fn send(coverage_data: &TraceMap, config: &Config) -> Result<(), String> {
+    if config.coveralls.is_none() {
         panic!("No coveralls key specified.");
     }
+    let report = build_report(coverage_data, config);
+    if config.flag.is_some() {
+        // CoverallsReport has no flag name so flagged reports are sent as the
+        // JSON written by `--out Coveralls`
+        println!("Sending report to endpoint: {}", report_uri(config));
+        return to_json(&report, config).and_then(|report| post(&report, config));
+    }
+    let res = match config.report_uri {
+        Some(ref uri) => {
+            println!("Sending report to endpoint: {}", uri);
+            report.send_to_endpoint(uri)
+        },
+        None => {
+            println!("Sending coverage data to coveralls.io");
+            report.send_to_coveralls()
+        }
+    };
+    res.map_err(|e| e.to_string())
+}
+
+fn report_uri(config: &Config) -> &str {
+    config.report_uri.as_ref().map(String::as_str).unwrap_or(COVERALLS_URI)
+}
+
+/// Uploads a report previously written with `--out Coveralls` to coveralls
+/// or the report URI. If a key is given it replaces the one in the report so
+/// the report can be written without the key, likewise for the flag.
+pub fn upload_file(path: &Path, config: &Config) -> Result<(), String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    let mut report: Value = serde_json::from_reader(file).map_err(|e| e.to_string())?;
+    if config.coveralls.is_some() || config.flag.is_some() {
+        // Take the identity fields from an empty report built with the key
+        let keyed = to_json(&build_report(&TraceMap::new(), config), config)?;
+        let fields: &[&str] = if config.coveralls.is_some() {
+            &["repo_token", "service_name", "service_job_id", "flag_name"]
+        } else {
+            &["flag_name"]
+        };
+        if let (Some(report), Some(keyed)) = (report.as_object_mut(), keyed.as_object()) {
+            for field in fields {
+                if let Some(value) = keyed.get(*field) {
+                    report.insert(field.to_string(), value.clone());
+                }
+            }
+        }
+    }
+    post(&report, config)
+}
+
+/// Posts a report already serialized to JSON to coveralls or the report URI,
+/// used for what `CoverallsReport` can't send: saved reports and flag names
+fn post(report: &Value, config: &Config) -> Result<(), String> {
+    let uri = report_uri(config);
+    let mut form = Form::new();
+    form.part("json_file")
+        .buffer("coveralls.json", report.to_string().into_bytes())
+        .add()
+        .map_err(|e| e.to_string())?;
+    let mut easy = Easy::new();
+    easy.url(uri).map_err(|e| e.to_string())?;
+    easy.httppost(form).map_err(|e| e.to_string())?;
+    let mut response = Vec::new();
+    {
+        let mut transfer = easy.transfer();
+        transfer.write_function(|data| {
+            response.extend_from_slice(data);
+            Ok(data.len())
+        }).map_err(|e| e.to_string())?;
+        transfer.perform().map_err(|e| e.to_string())?;
+    }
+    match easy.response_code().map_err(|e| e.to_string())? {
+        200..=299 => Ok(()),
+        code => Err(format!("{} responded with {}: {}", uri, code, String::from_utf8_lossy(&response))),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_validation() {
+        let mut config = Config::default();
+        assert!(validate_key(&config).is_ok());
+        config.coveralls = Some("aBc123XyZ".to_string());
+        assert!(validate_key(&config).is_ok());
+        config.coveralls = Some("  ".to_string());
+        assert!(validate_key(&config).is_err());
+        config.coveralls = Some("$TRAVIS_JOB_ID".to_string());
+        assert!(validate_key(&config).is_err());
+        config.ci_tool = Some(CiService::Travis);
+        assert!(validate_key(&config).is_err());
+        config.coveralls = Some("123456".to_string());
+        assert!(validate_key(&config).is_ok());
+        config.ci_tool = Some(CiService::Jenkins);
+        config.coveralls = Some("build-42".to_string());
+        assert!(validate_key(&config).is_ok());
+    }
+
+    #[test]
+    fn flagged_reports() {
+        let mut config = Config::default();
+        let report = build_report(&TraceMap::new(), &config);
+        assert!(to_json(&report, &config).unwrap().get("flag_name").is_none());
+        config.flag = Some("backend".to_string());
+        let report = to_json(&report, &config).unwrap();
+        assert_eq!(report["flag_name"], Value::String("backend".to_string()));
+    }
+
+    #[test]
+    fn exported_without_token() {
+        let mut config = Config::default();
+        config.coveralls = Some("aBc123XyZ".to_string());
+        let report = to_json(&build_report(&TraceMap::new(), &config), &config).unwrap();
+        assert_eq!(report["repo_token"], Value::String("aBc123XyZ".to_string()));
+        let exported = exported_json(&TraceMap::new(), &config).unwrap();
+        assert!(exported.get("repo_token").is_none());
+        assert!(!exported.to_string().contains("aBc123XyZ"));
+    }
 }
@@ -0,0 +1,122 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use traces::TraceMap;
+use config::{Config, PathStyle};
+use report::create_output;
+use report::json::trace_hits;
+
+
+const STYLE: &str = "body { font-family: sans-serif; }
+table { border-collapse: collapse; font-family: monospace; }
+td { padding: 0 0.5em; white-space: pre; }
+td.line { color: #888; text-align: right; }
+tr.covered { background-color: #cfc; }
+tr.uncovered { background-color: #fcc; }";
+
+
+/// Escapes the characters HTML would otherwise interpret
+fn escape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+/// Sums the hits of every trace of each line, with instruction granularity a
+/// line has several traces
+fn line_hits(coverage_data: &TraceMap, file: &Path) -> BTreeMap<u64, u64> {
+    let mut result = BTreeMap::new();
+    for trace in coverage_data.get_child_traces(file) {
+        *result.entry(trace.line).or_insert(0) += trace_hits(&trace.stats);
+    }
+    result
+}
+
+/// Renders the source of a file with its coverable lines coloured. Hovering a
+/// line shows how many times it was executed, which is only a count of the
+/// hits with `--count`. The file is named by its path relative to the root.
+fn render_file(coverage_data: &TraceMap, file: &Path, root: Option<&Path>, config: &Config) -> String {
+    let hits = line_hits(coverage_data, file);
+    let source = fs::read_to_string(file).unwrap_or_default();
+    let mut rows = String::new();
+    for (i, text) in source.lines().enumerate() {
+        let line = (i + 1) as u64;
+        let attributes = match hits.get(&line) {
+            Some(&0) => " class=\"uncovered\" title=\"0 hits\"".to_string(),
+            Some(&1) => " class=\"covered\" title=\"1 hit\"".to_string(),
+            Some(n) => format!(" class=\"covered\" title=\"{} hits\"", n),
+            None => String::new(),
+        };
+        rows.push_str(&format!("<tr{}><td class=\"line\">{}</td><td>{}</td></tr>\n",
+                               attributes, line, escape(text)));
+    }
+    format!("<h2>{} ({}/{})</h2>\n<table>\n{}</table>\n",
+            escape(&config.report_path(file, root).display().to_string()),
+            coverage_data.covered_in_path(file),
+            coverage_data.coverable_in_path(file),
+            rows)
+}
+
+/// Writes the coverage results to `tarpaulin-report.html` with the source of
+/// every file
+pub fn export(coverage_data: &TraceMap, config: &Config) {
+    let mut body = format!("<h1>{:.prec$}% coverage, {}/{} lines covered</h1>\n",
+                           coverage_data.coverage_percentage() * 100.0f64,
+                           coverage_data.total_covered(),
+                           coverage_data.total_coverable(),
+                           prec = config.precision());
+    let root = config.report_root(PathStyle::WorkspaceRelative);
+    for file in coverage_data.files() {
+        body.push_str(&render_file(coverage_data, file, root.as_ref().map(|r| r.as_path()), config));
+    }
+    let mut file = create_output("tarpaulin-report.html", config).unwrap();
+    write!(file, "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+                  <title>Tarpaulin coverage report</title>\n<style>\n{}\n</style>\n\
+                  </head>\n<body>\n{}</body>\n</html>\n", STYLE, body).unwrap();
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use traces::{Trace, CoverageStat};
+
+    #[test]
+    fn escaping() {
+        assert_eq!(escape("fn foo<T>(x: &T) -> \"a\""),
+                   "fn foo&lt;T&gt;(x: &amp;T) -&gt; &quot;a&quot;");
+    }
+
+    #[test]
+    fn hit_count_titles() {
+        let dir = ::std::env::temp_dir().join("tarpaulin-hit-count-titles");
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("lib.rs");
+        fs::write(&file, "fn a() {\n    b();\n    c();\n}\n").unwrap();
+        let file = file.as_path();
+        let mut coverage = TraceMap::new();
+        for &(line, hits) in &[(1, 3), (2, 1), (3, 0)] {
+            coverage.add_trace(file, Trace {
+                line,
+                address: None,
+                length: 1,
+                stats: CoverageStat::Line(hits),
+            });
+        }
+        let html = render_file(&coverage, file, None, &Config::default());
+        let _ = fs::remove_dir_all(&dir);
+        assert!(html.contains("<tr class=\"covered\" title=\"3 hits\"><td class=\"line\">1</td>"));
+        assert!(html.contains("<tr class=\"covered\" title=\"1 hit\"><td class=\"line\">2</td>"));
+        assert!(html.contains("<tr class=\"uncovered\" title=\"0 hits\"><td class=\"line\">3</td>"));
+        assert!(html.contains("<tr><td class=\"line\">4</td>"));
+    }
+}
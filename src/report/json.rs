@@ -0,0 +1,246 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use serde_json::{self, Value};
+use traces::{TraceMap, Trace, CoverageStat};
+use config::{Config, PathStyle};
+use report::create_output;
+
+
+pub(super) fn trace_hits(stats: &CoverageStat) -> u64 {
+    match *stats {
+        CoverageStat::Line(hits) => hits,
+        CoverageStat::Branch(ref l) => (l.been_true as u64) + (l.been_false as u64),
+        CoverageStat::Condition(ref c) => c.iter().filter(|l| l.been_true || l.been_false).count() as u64,
+    }
+}
+
+/// Groups the uncovered lines into the first and last line of each run not
+/// interrupted by a covered line, lines that aren't coverable don't end a run.
+fn uncovered_regions(lines: &BTreeMap<u64, bool>) -> Vec<(u64, u64)> {
+    let mut result: Vec<(u64, u64)> = vec![];
+    let mut in_region = false;
+    for (&line, &covered) in lines {
+        if covered {
+            in_region = false;
+        } else if in_region {
+            if let Some(region) = result.last_mut() {
+                region.1 = line;
+            }
+        } else {
+            result.push((line, line));
+            in_region = true;
+        }
+    }
+    result
+}
+
+/// The uncovered regions of a file with the source text of their lines
+fn embedded_source(file: &Path, traces: &[Trace]) -> Vec<Value> {
+    let mut lines = BTreeMap::new();
+    for t in traces {
+        *lines.entry(t.line).or_insert(false) |= trace_hits(&t.stats) > 0;
+    }
+    let source = fs::read_to_string(file).unwrap_or_default();
+    let source = source.lines().collect::<Vec<_>>();
+    uncovered_regions(&lines).into_iter()
+        .map(|(start, end)| {
+            let text = source.iter()
+                             .skip(start.saturating_sub(1) as usize)
+                             .take((end - start + 1) as usize)
+                             .cloned()
+                             .collect::<Vec<_>>();
+            json!({
+                "start": start,
+                "end": end,
+                "source": text.join("\n"),
+            })
+        })
+        .collect()
+}
+
+/// The coverage record of a single file
+fn file_json(coverage_data: &TraceMap,
+             file: &Path,
+             traces: &[Trace],
+             root: Option<&Path>,
+             config: &Config) -> Value {
+    let mut result = json!({
+        "path": config.report_path(file, root),
+        "covered": coverage_data.covered_in_path(file),
+        "coverable": coverage_data.coverable_in_path(file),
+        "traces": traces.iter()
+                        .map(|t| json!({
+                            "line": t.line,
+                            "address": t.address,
+                            "length": t.length,
+                            "hits": trace_hits(&t.stats),
+                        }))
+                        .collect::<Vec<_>>(),
+    });
+    if config.embed_source {
+        result["uncovered_regions"] = Value::Array(embedded_source(file, traces));
+    }
+    result
+}
+
+fn to_json(coverage_data: &TraceMap, config: &Config) -> Value {
+    let root = config.report_root(PathStyle::WorkspaceRelative);
+    let files = coverage_data.iter()
+        .map(|(file, traces)| {
+            file_json(coverage_data, file, traces, root.as_ref().map(|r| r.as_path()), config)
+        })
+        .collect::<Vec<Value>>();
+    json!({
+        "coverage": coverage_data.coverage_percentage(),
+        "covered": coverage_data.total_covered(),
+        "coverable": coverage_data.total_coverable(),
+        "files": files,
+    })
+}
+
+/// Writes the coverage results to `tarpaulin-report.json`. Each file lists its
+/// traces with their address so with instruction granularity every traced
+/// address of a line is reported with its own hit count. With
+/// `--embed-source` each file also lists its uncovered regions and their
+/// source.
+pub fn export(coverage_data: &TraceMap, config: &Config) {
+    let mut file = create_output("tarpaulin-report.json", config).unwrap();
+    serde_json::to_writer_pretty(&mut file, &to_json(coverage_data, config)).unwrap();
+    file.write_all(b"\n").unwrap();
+}
+
+/// Writes the record of each file on its own line as soon as it's serialized
+fn write_lines<W: Write>(coverage_data: &TraceMap, config: &Config, output: &mut W) -> io::Result<()> {
+    let root = config.report_root(PathStyle::WorkspaceRelative);
+    for (file, traces) in coverage_data.iter() {
+        let record = file_json(coverage_data, file, traces, root.as_ref().map(|r| r.as_path()), config);
+        serde_json::to_writer(&mut *output, &record)?;
+        output.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+/// Writes the coverage results to `tarpaulin-report.jsonl`, every line is the
+/// same record a file has in the JSON report so the results can be processed
+/// a file at a time.
+pub fn export_lines(coverage_data: &TraceMap, config: &Config) {
+    let mut file = create_output("tarpaulin-report.jsonl", config).unwrap();
+    write_lines(coverage_data, config, &mut file).unwrap();
+}
+
+/// Saves the JSON report uncompressed to the given path to be compared
+/// against by later runs
+pub fn save(coverage_data: &TraceMap, config: &Config, path: &Path) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    serde_json::to_writer_pretty(&mut file, &to_json(coverage_data, config))?;
+    file.write_all(b"\n")
+}
+
+/// Loads the overall coverage of a saved JSON report as a fraction
+pub fn load_coverage(path: &Path) -> io::Result<f64> {
+    let report: Value = serde_json::from_reader(File::open(path)?)?;
+    report["coverage"].as_f64().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "report has no overall coverage")
+    })
+}
+
+/// Loads a saved JSON report returning whether each line of every file was
+/// covered. Paths are relative to the project root.
+pub fn load_line_coverage(path: &Path) -> io::Result<BTreeMap<PathBuf, BTreeMap<u64, bool>>> {
+    let report: Value = serde_json::from_reader(File::open(path)?)?;
+    let mut result = BTreeMap::new();
+    let files = report["files"].as_array().cloned().unwrap_or_default();
+    for file in &files {
+        let path = match file["path"].as_str() {
+            Some(p) => PathBuf::from(p),
+            None => continue,
+        };
+        let lines = result.entry(path).or_insert_with(BTreeMap::new);
+        for t in file["traces"].as_array().map(|x| x.as_slice()).unwrap_or(&[]) {
+            if let Some(line) = t["line"].as_u64() {
+                let hit = t["hits"].as_u64().unwrap_or(0) > 0;
+                *lines.entry(line).or_insert(false) |= hit;
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Gets whether each line of every file in the coverage results was covered,
+/// the paths are written as they are in the JSON report
+pub fn line_coverage(coverage_data: &TraceMap, config: &Config) -> BTreeMap<PathBuf, BTreeMap<u64, bool>> {
+    let root = config.report_root(PathStyle::WorkspaceRelative);
+    let mut result = BTreeMap::new();
+    for (file, traces) in coverage_data.iter() {
+        let path = config.report_path(file, root.as_ref().map(|r| r.as_path()));
+        let lines = result.entry(path).or_insert_with(BTreeMap::new);
+        for t in traces {
+            *lines.entry(t.line).or_insert(false) |= trace_hits(&t.stats) > 0;
+        }
+    }
+    result
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regions() {
+        let lines = [(1, false), (2, false), (4, false), (5, true), (6, false), (9, true)]
+            .iter()
+            .cloned()
+            .collect::<BTreeMap<u64, bool>>();
+        assert_eq!(uncovered_regions(&lines), vec![(1, 4), (6, 6)]);
+        assert!(uncovered_regions(&BTreeMap::new()).is_empty());
+    }
+
+    #[test]
+    fn region_source() {
+        let dir = ::std::env::temp_dir().join("tarpaulin-region-source");
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("lib.rs");
+        fs::write(&file, "fn covered() {}\n\nfn first() {}\nfn second() {}\n").unwrap();
+        let traces = [3, 4].iter()
+                           .map(|&line| Trace {
+                               line,
+                               address: None,
+                               length: 1,
+                               stats: CoverageStat::Line(0),
+                           })
+                           .collect::<Vec<_>>();
+        let regions = embedded_source(&file, &traces);
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(regions.len(), 1);
+        assert_eq!(regions[0]["source"], "fn first() {}\nfn second() {}");
+    }
+
+    #[test]
+    fn json_lines() {
+        let mut coverage = TraceMap::new();
+        for &(file, line) in &[("/p/src/lib.rs", 1), ("/p/src/lib.rs", 2), ("/p/src/main.rs", 1)] {
+            coverage.add_trace(Path::new(file), Trace {
+                line,
+                address: None,
+                length: 1,
+                stats: CoverageStat::Line(line - 1),
+            });
+        }
+        let mut config = Config::default();
+        config.manifest = PathBuf::from("/p/Cargo.toml");
+        let mut output = vec![];
+        write_lines(&coverage, &config, &mut output).unwrap();
+        let records = String::from_utf8(output).unwrap()
+                                               .lines()
+                                               .map(|l| serde_json::from_str::<Value>(l).unwrap())
+                                               .collect::<Vec<_>>();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0]["path"], "src/lib.rs");
+        assert_eq!(records[0]["covered"], 1);
+        assert_eq!(records[0]["coverable"], 2);
+        assert_eq!(records[1]["path"], "src/main.rs");
+    }
+}
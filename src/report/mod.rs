@@ -1,9 +1,20 @@
-use test_loader::TracerData;
-use config::Config;
+use std::fs::File;
+use std::io::{self, Write};
+use std::marker::PhantomData;
+use flate2::write::GzEncoder;
+use flate2::Compression as Level;
+use test_loader::{LineType, TracerData};
+use traces::TraceMap;
+use config::{Config, Compression};
 use serde::Serialize;
 
 pub mod cobertura;
+pub mod codecov;
 pub mod coveralls;
+pub mod html;
+pub mod json;
+pub mod sqlite;
+pub mod toml;
 /// Trait for report formats to implement.
 /// Currently reports must be serializable using serde
 pub trait Report<Out: Serialize> {
@@ -11,3 +22,116 @@ pub trait Report<Out: Serialize> {
     fn export(coverage_data: &[TracerData], config: &Config);
 
 }
+
+/// Object safe report so programs using tarpaulin as a library can give their
+/// own reports to `run_with_reports`, they're exported with the final coverage
+/// results. Implemented for closures taking the results and config.
+pub trait DynReport {
+    /// Export coverage report
+    fn export(&self, coverage_data: &TraceMap, config: &Config);
+}
+
+impl<F> DynReport for F where F: Fn(&TraceMap, &Config) {
+    fn export(&self, coverage_data: &TraceMap, config: &Config) {
+        (self)(coverage_data, config)
+    }
+}
+
+/// Lets a `Report` be given to `run_with_reports`. A `Report` is exported
+/// from the tracer data so it's given the address and length of every trace,
+/// the hits and files aren't part of the tracer data.
+pub struct ReportAdapter<T, Out> {
+    report: PhantomData<fn() -> (T, Out)>,
+}
+
+impl<T, Out> ReportAdapter<T, Out> where T: Report<Out>, Out: Serialize {
+    pub fn new() -> Self {
+        ReportAdapter {
+            report: PhantomData,
+        }
+    }
+}
+
+impl<T, Out> Default for ReportAdapter<T, Out> where T: Report<Out>, Out: Serialize {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, Out> DynReport for ReportAdapter<T, Out> where T: Report<Out>, Out: Serialize {
+    fn export(&self, coverage_data: &TraceMap, config: &Config) {
+        let data = coverage_data.all_traces()
+                                .iter()
+                                .map(|t| TracerData {
+                                    trace_type: LineType::Statement,
+                                    address: t.address,
+                                    length: t.length,
+                                })
+                                .collect::<Vec<_>>();
+        T::export(&data, config);
+    }
+}
+
+/// Gets the file name a report is written to, prefixed with the report name
+/// if one was given so the reports of different runs don't collide
+pub fn output_name(name: &str, config: &Config) -> String {
+    match config.report_name {
+        Some(ref prefix) => format!("{}-{}", prefix, name),
+        None => name.to_string(),
+    }
+}
+
+/// Creates the file a report is written to. If compression is enabled the
+/// output is gzipped and `.gz` is appended to the file name.
+pub fn create_output(name: &str, config: &Config) -> io::Result<Box<Write>> {
+    let name = output_name(name, config);
+    match config.compress {
+        Some(Compression::Gzip) => {
+            let file = File::create(format!("{}.gz", name))?;
+            Ok(Box::new(GzEncoder::new(file, Level::default())))
+        },
+        None => Ok(Box::new(File::create(name)?)),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_names() {
+        let mut config = Config::default();
+        assert_eq!(output_name("cobertura.xml", &config), "cobertura.xml");
+        config.report_name = Some("integration".to_string());
+        assert_eq!(output_name("cobertura.xml", &config), "integration-cobertura.xml");
+    }
+
+    #[test]
+    fn adapted_report() {
+        use std::path::Path;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use traces::{Trace, CoverageStat};
+
+        static EXPORTED: AtomicUsize = AtomicUsize::new(0);
+        struct Count;
+        impl Report<usize> for Count {
+            fn export(coverage_data: &[TracerData], _: &Config) {
+                EXPORTED.store(coverage_data.len(), Ordering::SeqCst);
+            }
+        }
+
+        let mut coverage = TraceMap::new();
+        for line in 1..4 {
+            coverage.add_trace(Path::new("src/lib.rs"), Trace {
+                line,
+                address: Some(line * 8),
+                length: 1,
+                stats: CoverageStat::Line(0),
+            });
+        }
+        let report: Box<DynReport> = Box::new(ReportAdapter::<Count, usize>::new());
+        report.export(&coverage, &Config::default());
+        assert_eq!(EXPORTED.load(Ordering::SeqCst), 3);
+    }
+}
@@ -0,0 +1,68 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use rusqlite::{Connection, Result};
+use rusqlite::types::ToSql;
+use traces::{TraceMap, CoverageStat};
+use config::{Config, PathStyle};
+use report::output_name;
+
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS runs (
+        id INTEGER PRIMARY KEY,
+        timestamp INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS files (
+        run INTEGER NOT NULL REFERENCES runs(id),
+        path TEXT NOT NULL,
+        covered INTEGER NOT NULL,
+        coverable INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS lines (
+        run INTEGER NOT NULL REFERENCES runs(id),
+        path TEXT NOT NULL,
+        line INTEGER NOT NULL,
+        hits INTEGER NOT NULL,
+        trace_type TEXT NOT NULL
+    );";
+
+
+fn write_run(conn: &mut Connection, coverage_data: &TraceMap, config: &Config) -> Result<()> {
+    let tx = conn.transaction()?;
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)
+                                     .map(|d| d.as_secs() as i64)
+                                     .unwrap_or(0);
+    tx.execute("INSERT INTO runs (timestamp) VALUES (?1)", &[&timestamp as &ToSql])?;
+    let run = tx.last_insert_rowid();
+    let root = config.report_root(PathStyle::WorkspaceRelative);
+    for (file, traces) in coverage_data.iter() {
+        let path = config.report_path(file, root.as_ref().map(|r| r.as_path())).display().to_string();
+        tx.execute("INSERT INTO files (run, path, covered, coverable) VALUES (?1, ?2, ?3, ?4)",
+                   &[&run as &ToSql,
+                     &path,
+                     &(coverage_data.covered_in_path(file) as i64),
+                     &(coverage_data.coverable_in_path(file) as i64)])?;
+        for t in traces {
+            let (hits, trace_type) = match t.stats {
+                CoverageStat::Line(hits) => (hits as i64, "line"),
+                CoverageStat::Branch(ref l) => ((l.been_true as i64) + (l.been_false as i64), "branch"),
+                CoverageStat::Condition(ref c) => (c.iter().filter(|l| l.been_true || l.been_false).count() as i64,
+                                                   "condition"),
+            };
+            tx.execute("INSERT INTO lines (run, path, line, hits, trace_type) VALUES (?1, ?2, ?3, ?4, ?5)",
+                       &[&run as &ToSql, &path, &(t.line as i64), &hits, &trace_type])?;
+        }
+    }
+    tx.commit()
+}
+
+/// Appends the coverage results to `coverage.db`. Every run is recorded
+/// separately so results can be compared between runs.
+pub fn export(coverage_data: &TraceMap, config: &Config) {
+    let result = Connection::open(output_name("coverage.db", config)).and_then(|mut conn| {
+        conn.execute_batch(SCHEMA)?;
+        write_run(&mut conn, coverage_data, config)
+    });
+    if let Err(e) = result {
+        println!("Failed to write coverage database: {}", e);
+    }
+}
@@ -0,0 +1,99 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use toml::{self, Value};
+use toml::value::Table;
+use traces::TraceMap;
+use config::{Config, PathStyle};
+use report::create_output;
+use report::json::trace_hits;
+
+
+/// Builds the report, its schema is
+///
+/// ```toml
+/// [summary]
+/// covered = 3      # lines covered in all files
+/// total = 4        # coverable lines in all files
+/// coverage = 0.75  # covered divided by total, 0.0 if nothing is coverable
+///
+/// [[files]]
+/// path = "src/lib.rs"
+/// covered = 3
+/// total = 4
+/// lines = [[1, 2], [2, 0]]  # line number and hits of each coverable line
+/// ```
+///
+/// Files and lines are in ascending order so the same results always give the
+/// same report.
+fn to_toml(coverage_data: &TraceMap, config: &Config) -> Value {
+    let root = config.report_root(PathStyle::WorkspaceRelative);
+    let files = coverage_data.iter()
+        .map(|(file, traces)| {
+            let mut hits = BTreeMap::new();
+            for t in traces {
+                *hits.entry(t.line).or_insert(0) += trace_hits(&t.stats);
+            }
+            let lines = hits.into_iter()
+                            .map(|(line, hits)| {
+                                Value::Array(vec![Value::Integer(line as i64),
+                                                  Value::Integer(hits as i64)])
+                            })
+                            .collect();
+            let path = config.report_path(file, root.as_ref().map(|r| r.as_path()));
+            let mut table = Table::new();
+            table.insert("path".to_string(), Value::String(path.display().to_string()));
+            table.insert("covered".to_string(), Value::Integer(coverage_data.covered_in_path(file) as i64));
+            table.insert("total".to_string(), Value::Integer(coverage_data.coverable_in_path(file) as i64));
+            table.insert("lines".to_string(), Value::Array(lines));
+            Value::Table(table)
+        })
+        .collect();
+    let mut summary = Table::new();
+    summary.insert("covered".to_string(), Value::Integer(coverage_data.total_covered() as i64));
+    summary.insert("total".to_string(), Value::Integer(coverage_data.total_coverable() as i64));
+    summary.insert("coverage".to_string(), Value::Float(coverage_data.coverage_percentage()));
+    let mut report = Table::new();
+    report.insert("summary".to_string(), Value::Table(summary));
+    report.insert("files".to_string(), Value::Array(files));
+    Value::Table(report)
+}
+
+/// Writes the coverage results to `tarpaulin-report.toml`
+pub fn export(coverage_data: &TraceMap, config: &Config) {
+    let report = toml::to_string(&to_toml(coverage_data, config)).unwrap();
+    let mut file = create_output("tarpaulin-report.toml", config).unwrap();
+    file.write_all(report.as_bytes()).unwrap();
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use traces::{Trace, CoverageStat};
+
+    #[test]
+    fn round_trip() {
+        let mut coverage = TraceMap::new();
+        for &(file, line, hits) in &[("/p/src/main.rs", 3, 0), ("/p/src/lib.rs", 2, 0), ("/p/src/lib.rs", 1, 2)] {
+            coverage.add_trace(Path::new(file), Trace {
+                line,
+                address: None,
+                length: 1,
+                stats: CoverageStat::Line(hits),
+            });
+        }
+        let mut config = Config::default();
+        config.manifest = Path::new("/p/Cargo.toml").to_path_buf();
+        let report = to_toml(&coverage, &config);
+        let text = toml::to_string(&report).unwrap();
+        assert_eq!(toml::from_str::<Value>(&text).unwrap(), report);
+        assert_eq!(report["summary"]["covered"].as_integer(), Some(1));
+        assert_eq!(report["summary"]["total"].as_integer(), Some(3));
+        assert_eq!(report["files"][0]["path"].as_str(), Some("src/lib.rs"));
+        assert_eq!(report["files"][0]["lines"][0], Value::Array(vec![Value::Integer(1), Value::Integer(2)]));
+        assert_eq!(report["files"][1]["path"].as_str(), Some("src/main.rs"));
+        // Serializing the same results again gives the same report
+        assert_eq!(toml::to_string(&to_toml(&coverage, &config)).unwrap(), text);
+    }
+}
@@ -8,7 +8,8 @@ use cargo::core::Workspace;
 use syn::{*, punctuated::{Pair::End, Pair}, spanned::Spanned, punctuated::Punctuated, token::Comma};
 use proc_macro2::{Span, TokenTree, TokenStream};
 use regex::Regex;
-use config::Config;
+use config::{Config, MacroCoverage};
+use test_loader::normalize_root;
 use walkdir::{DirEntry, WalkDir};
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
@@ -28,6 +29,12 @@ pub struct LineAnalysis {
     /// But may be ignored. Doesn't make sense to cover ALL the lines so this
     /// is just an index.
     pub cover: HashSet<usize>,
+    /// Test functions marked `#[should_panic]` by their path from the crate
+    /// root, which is the name libtest gives them
+    pub should_panic: HashSet<String>,
+    /// Lines of const fns that are only evaluated at compile time, these are
+    /// also ignored
+    pub const_evaluated: HashSet<usize>,
 }
 
 /// When the LineAnalysis results are mapped to their files there needs to be
@@ -60,7 +67,9 @@ impl LineAnalysis {
     fn new() -> LineAnalysis {
         LineAnalysis {
             ignore: HashSet::new(),
-            cover: HashSet::new()
+            cover: HashSet::new(),
+            should_panic: HashSet::new(),
+            const_evaluated: HashSet::new(),
         }
     }
 
@@ -141,6 +150,220 @@ impl LineAnalysis {
 }
 
 
+/// A const fn found in the workspace
+#[derive(Debug)]
+struct ConstFn {
+    /// Path from the crate root, methods are under their type's name
+    path: Vec<String>,
+    /// File the fn is in
+    file: PathBuf,
+    /// First line of the fn
+    start: usize,
+    /// Last line of the fn
+    end: usize,
+    /// Paths used in the body, they're only evaluated at runtime if the fn is
+    uses: Vec<Vec<String>>,
+}
+
+/// Const fns in the workspace and the paths used in const and runtime code.
+/// A const fn only used from const and static initializers or other const
+/// fns evaluated at compile time is never ran by the tests, so it's flagged as
+/// const evaluated instead of counting as uncovered.
+#[derive(Debug, Default)]
+struct ConstEvaluation {
+    /// Every const fn in the workspace
+    const_fns: Vec<ConstFn>,
+    /// Paths used in const and static initializers
+    const_uses: HashSet<Vec<String>>,
+    /// Paths used outside of const items
+    runtime_uses: HashSet<Vec<String>>,
+}
+
+impl ConstEvaluation {
+    /// Records the const fns in the file and sorts the paths used in it into
+    /// const, runtime and const fn body uses. Any use of a name counts, not
+    /// just calls, so a const fn passed as a value is evaluated at runtime.
+    /// Paths are read from the tokens so comments and literals are skipped.
+    fn add_file(&mut self, file: &Path, items: &[Item], content: &str) {
+        let first_fn = self.const_fns.len();
+        let mut const_lines = vec![];
+        find_const_items(items, &mut file_module_path(file), &mut const_lines, &mut self.const_fns, file);
+        let tokens = match content.parse::<TokenStream>() {
+            Ok(t) => t,
+            Err(_) => return,
+        };
+        let mut paths = vec![];
+        token_paths(tokens, &mut paths);
+        for (line, path) in paths {
+            // Const fns can be nested in other items so the innermost is used
+            let const_fn = self.const_fns[first_fn..].iter_mut()
+                                                     .filter(|f| f.start <= line && line <= f.end)
+                                                     .min_by_key(|f| f.end - f.start);
+            if let Some(f) = const_fn {
+                f.uses.push(path);
+            } else if const_lines.iter().any(|&(start, end)| start <= line && line <= end) {
+                self.const_uses.insert(path);
+            } else {
+                self.runtime_uses.insert(path);
+            }
+        }
+    }
+
+    /// Ignores the lines of const fns only evaluated at compile time and flags
+    /// them as const evaluated
+    fn apply(&self, result: &mut HashMap<PathBuf, LineAnalysis>) {
+        let fns = &self.const_fns;
+        let used_by = |uses: &[Vec<String>], f: &ConstFn| uses.iter().any(|p| is_path_to(p, &f.path));
+        let mut runtime = fns.iter()
+                             .map(|f| self.runtime_uses.iter().any(|p| is_path_to(p, &f.path)))
+                             .collect::<Vec<_>>();
+        // A const fn used by one evaluated at runtime is evaluated at runtime
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for i in 0..fns.len() {
+                if !runtime[i] && (0..fns.len()).any(|j| runtime[j] && used_by(&fns[j].uses, &fns[i])) {
+                    runtime[i] = true;
+                    changed = true;
+                }
+            }
+        }
+        for (i, f) in fns.iter().enumerate() {
+            let const_used = self.const_uses.iter().any(|p| is_path_to(p, &f.path)) ||
+                             fns.iter().enumerate().any(|(j, g)| i != j && used_by(&g.uses, f));
+            if runtime[i] || !const_used {
+                continue;
+            }
+            let lines = (f.start..(f.end+1)).collect::<Vec<_>>();
+            let analysis = result.entry(f.file.clone()).or_insert_with(LineAnalysis::new);
+            analysis.add_to_ignore(&lines);
+            analysis.const_evaluated.extend(lines);
+        }
+    }
+}
+
+/// Whether a path used in the source could name the const fn. Imports aren't
+/// resolved so the path only has to match the end of the const fn's path.
+fn is_path_to(path: &[String], const_fn: &[String]) -> bool {
+    !path.is_empty() && const_fn.ends_with(path)
+}
+
+/// Collects the paths in the tokens with the line they start on. A path is
+/// identifiers joined by `::`, with any leading `crate`, `self`, `super` or
+/// `Self` dropped. The name after `fn` is a declaration rather than a use.
+fn token_paths(tokens: TokenStream, paths: &mut Vec<(usize, Vec<String>)>) {
+    let mut current: Vec<String> = vec![];
+    let mut line = 0;
+    let mut colons = 0;
+    let mut declaration = false;
+    for token in tokens {
+        let joined = match token {
+            TokenTree::Ident(_) => colons == 2,
+            TokenTree::Punct(ref p) => p.as_char() == ':',
+            _ => false,
+        };
+        if !joined && !current.is_empty() {
+            paths.push((line, current.clone()));
+            current.clear();
+        }
+        match token {
+            TokenTree::Ident(ref ident) => {
+                let name = ident.to_string();
+                colons = 0;
+                if declaration {
+                    declaration = false;
+                } else if name == "fn" {
+                    declaration = true;
+                } else if !(current.is_empty() && ["crate", "self", "super", "Self"].contains(&name.as_str())) {
+                    if current.is_empty() {
+                        line = ident.span().start().line;
+                    }
+                    current.push(name);
+                }
+            },
+            TokenTree::Punct(ref p) if p.as_char() == ':' => colons += 1,
+            TokenTree::Group(ref g) => {
+                colons = 0;
+                declaration = false;
+                token_paths(g.stream(), paths);
+            },
+            _ => {
+                colons = 0;
+                declaration = false;
+            },
+        }
+    }
+    if !current.is_empty() {
+        paths.push((line, current));
+    }
+}
+
+/// Finds the line ranges of const and static items and the const fns,
+/// descending into inline modules, traits and impls
+fn find_const_items(items: &[Item],
+                    module: &mut Vec<String>,
+                    const_lines: &mut Vec<(usize, usize)>,
+                    const_fns: &mut Vec<ConstFn>,
+                    file: &Path) {
+    let add_fn = |path: Vec<String>, span: Span, const_fns: &mut Vec<ConstFn>| {
+        const_fns.push(ConstFn {
+            path,
+            file: file.to_path_buf(),
+            start: span.start().line,
+            end: span.end().line,
+            uses: vec![],
+        });
+    };
+    let path_to = |module: &[String], names: &[String]| {
+        module.iter().chain(names).cloned().collect::<Vec<_>>()
+    };
+    for item in items {
+        match *item {
+            Item::Const(ref i) => const_lines.push((i.span().start().line, i.span().end().line)),
+            Item::Static(ref i) => const_lines.push((i.span().start().line, i.span().end().line)),
+            Item::Fn(ref i) if i.constness.is_some() => {
+                add_fn(path_to(&module[..], &[i.ident.to_string()]), i.span(), const_fns);
+            },
+            Item::Mod(ref i) => {
+                if let Some((_, ref items)) = i.content {
+                    module.push(i.ident.to_string());
+                    find_const_items(items, module, const_lines, const_fns, file);
+                    module.pop();
+                }
+            },
+            Item::Trait(ref i) => {
+                for item in &i.items {
+                    if let TraitItem::Const(ref c) = *item {
+                        const_lines.push((c.span().start().line, c.span().end().line));
+                    }
+                }
+            },
+            Item::Impl(ref i) => {
+                let self_ty = match *i.self_ty {
+                    Type::Path(ref p) => p.path.segments.iter().last().map(|s| s.ident.to_string()),
+                    _ => None,
+                };
+                for item in &i.items {
+                    match *item {
+                        ImplItem::Const(ref c) => {
+                            const_lines.push((c.span().start().line, c.span().end().line));
+                        },
+                        ImplItem::Method(ref m) if m.sig.constness.is_some() => {
+                            let names = self_ty.iter().cloned()
+                                               .chain(Some(m.sig.ident.to_string()))
+                                               .collect::<Vec<_>>();
+                            add_fn(path_to(&module[..], &names), m.span(), const_fns);
+                        },
+                        _ => {},
+                    }
+                }
+            },
+            _ => {},
+        }
+    }
+}
+
+
 fn is_source_file(entry: &DirEntry) -> bool {
     let p = entry.path();
     p.extension() == Some(OsStr::new("rs"))
@@ -157,13 +380,15 @@ pub fn get_line_analysis(project: &Workspace, config: &Config) -> HashMap<PathBu
     let mut result: HashMap<PathBuf, LineAnalysis> = HashMap::new();
 
     let mut ignored_files: HashSet<PathBuf> = HashSet::new();
+    let mut consts = ConstEvaluation::default();
 
-    let walker = WalkDir::new(project.root()).into_iter();
-    for e in walker.filter_entry(|e| !is_target_folder(e, project.root()))
+    let root = normalize_root(project.root());
+    let walker = WalkDir::new(&root).follow_links(true).into_iter();
+    for e in walker.filter_entry(|e| !is_target_folder(e, &root))
                    .filter_map(|e| e.ok())
                    .filter(|e| is_source_file(e)) {
         if !ignored_files.contains(e.path()) {
-            analyse_package(e.path(), project.root(), &config, &mut result, &mut ignored_files);
+            analyse_package(e.path(), &root, &config, &mut result, &mut ignored_files, &mut consts);
         } else {
             let mut analysis = LineAnalysis::new();
             analysis.ignore_all();
@@ -176,6 +401,7 @@ pub fn get_line_analysis(project: &Workspace, config: &Config) -> HashMap<PathBu
         analysis.ignore_all();
         result.insert(e.to_path_buf(), analysis);
     }
+    consts.apply(&mut result);
     result
 }
 
@@ -219,12 +445,14 @@ fn analyse_package(path: &Path,
                    root: &Path,
                    config:&Config,
                    result: &mut HashMap<PathBuf, LineAnalysis>,
-                   filtered_files: &mut HashSet<PathBuf>) {
+                   filtered_files: &mut HashSet<PathBuf>,
+                   consts: &mut ConstEvaluation) {
 
     if let Some(file) = path.to_str() {
         let skip_cause_test = config.ignore_tests &&
                               path.starts_with(root.join("tests"));
-        let skip_cause_example = path.starts_with(root.join("examples"));
+        let skip_cause_example = !(config.all_targets || config.example.is_some()) &&
+                                 path.starts_with(root.join("examples"));
         if !(skip_cause_test || skip_cause_example)  {
             let file = File::open(file);
             if let Ok(mut file) =  file {
@@ -242,6 +470,10 @@ fn analyse_package(path: &Path,
 
                     find_ignorable_lines(&content, &mut analysis);
                     process_items(&file.items, &ctx, &mut analysis);
+                    find_should_panic(&file.items, &mut file_module_path(path), &mut analysis.should_panic);
+                    find_excluded_lines(&content, config, &mut analysis);
+                    find_skip_regions(&content, path, &mut analysis);
+                    consts.add_file(path, &file.items, &content);
                     // Check there's no conflict!
                     result.insert(path.to_path_buf(), analysis);
 
@@ -268,19 +500,234 @@ fn analyse_package(path: &Path,
     }
 }
 
+/// Gets the module path of a source file from where it is in its package.
+/// Crate roots like `src/lib.rs`, `src/bin/tool.rs` or an integration test
+/// are the root of their module tree.
+fn file_module_path(file: &Path) -> Vec<String> {
+    let package = match file.ancestors().skip(1).find(|d| d.join("Cargo.toml").is_file()) {
+        Some(p) => p,
+        None => return vec![],
+    };
+    let mut parts = match file.with_extension("").strip_prefix(package) {
+        Ok(p) => p.iter().map(|c| c.to_string_lossy().into_owned()).collect::<Vec<_>>(),
+        Err(_) => return vec![],
+    };
+    // Every file directly in src/bin, tests, examples or benches is a crate
+    let bin = parts.len() > 2 && parts[0] == "src" && parts[1] == "bin";
+    let crate_per_file = bin || parts[0] != "src";
+    let target_len = if bin { 2 } else { 1 };
+    let mut modules = parts.split_off(target_len);
+    if crate_per_file {
+        if modules.len() < 2 {
+            return vec![];
+        }
+        // A crate in a directory of its own like tests/api/main.rs
+        let target_dir = parts.iter().fold(package.to_path_buf(), |dir, p| dir.join(p));
+        if target_dir.join(&modules[0]).join("main.rs").is_file() {
+            modules.remove(0);
+        }
+    }
+    if modules.last().map_or(false, |m| m == "mod") {
+        modules.pop();
+    }
+    if modules.len() == 1 && (modules[0] == "lib" || modules[0] == "main") {
+        modules.clear();
+    }
+    modules
+}
+
+/// Records the tests marked `#[should_panic]` by their path from the crate
+/// root, descending into inline modules
+fn find_should_panic(items: &[Item], module: &mut Vec<String>, tests: &mut HashSet<String>) {
+    for item in items {
+        match *item {
+            Item::Fn(ref func) => {
+                let has_attr = |name: &str| {
+                    func.attrs.iter()
+                              .filter_map(|a| a.interpret_meta())
+                              .any(|m| m.name() == name)
+                };
+                if has_attr("test") && has_attr("should_panic") {
+                    let mut path = module.clone();
+                    path.push(func.ident.to_string());
+                    tests.insert(path.join("::"));
+                }
+            },
+            Item::Mod(ref m) => {
+                if let Some((_, ref items)) = m.content {
+                    module.push(m.ident.to_string());
+                    find_should_panic(items, module, tests);
+                    module.pop();
+                }
+            },
+            _ => {},
+        }
+    }
+}
+
 /// Finds lines from the raw string which are ignorable.
 /// These are often things like close braces, semi colons that may regiser as
-/// false positives.
+/// false positives. A trailing line comment, like `} // end loop`, doesn't
+/// make the line coverable.
 fn find_ignorable_lines(content: &str, analysis: &mut LineAnalysis) {
+    let lines = strip_line_comments(content).iter()
+                                            .enumerate()
+                                            .filter(|&(_, x)| !x.chars().any(|x| !"(){}[]?;\t ,".contains(x)))
+                                            .map(|(i, _)| i+1)
+                                            .collect::<Vec<usize>>();
+    analysis.add_to_ignore(&lines);
+}
+
+/// Splits the content into lines with their line comments removed. A `//`
+/// inside a string or char literal, including strings spanning several lines
+/// and raw strings, doesn't start a comment.
+fn strip_line_comments(content: &str) -> Vec<String> {
+    let mut lines = vec![];
+    let mut line = String::new();
+    // In a string, with the number of hashes closing it if it's a raw string
+    let mut string: Option<Option<usize>> = None;
+    let mut comment = false;
+    let mut prev = ' ';
+    let mut chars = content.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\n' {
+            lines.push(line.trim_end_matches('\r').to_string());
+            line.clear();
+            comment = false;
+            prev = c;
+            continue;
+        }
+        if comment {
+            continue;
+        }
+        line.push(c);
+        match string {
+            Some(None) => {
+                if c == '\\' {
+                    // The escaped character, unless it's the newline of a
+                    // string continued on the next line
+                    if let Some(&next) = chars.peek() {
+                        if next != '\n' {
+                            line.push(next);
+                            chars.next();
+                        }
+                    }
+                } else if c == '"' {
+                    string = None;
+                }
+            },
+            Some(Some(hashes)) => {
+                if c == '"' {
+                    let rest = chars.clone().take(hashes).take_while(|&h| h == '#').count();
+                    if rest == hashes {
+                        line.extend(chars.by_ref().take(hashes));
+                        string = None;
+                    }
+                }
+            },
+            None => {
+                let is_ident = prev.is_alphanumeric() || prev == '_';
+                if c == '/' && chars.peek() == Some(&'/') {
+                    line.pop();
+                    comment = true;
+                } else if c == '"' {
+                    string = Some(None);
+                } else if c == 'r' && (!is_ident || prev == 'b') {
+                    let hashes = chars.clone().take_while(|&h| h == '#').count();
+                    if chars.clone().nth(hashes) == Some('"') {
+                        line.extend(chars.by_ref().take(hashes + 1));
+                        string = Some(Some(hashes));
+                    }
+                } else if c == '\'' {
+                    // A char literal rather than a lifetime, its character
+                    // may be a quote or an escape
+                    let mut ahead = chars.clone();
+                    let literal_len = match (ahead.next(), ahead.next()) {
+                        (Some('\\'), _) => chars.clone().skip(2).position(|x| x == '\'').map(|i| i + 3),
+                        (Some(_), Some('\'')) => Some(2),
+                        _ => None,
+                    };
+                    if let Some(len) = literal_len {
+                        line.extend(chars.by_ref().take(len));
+                    }
+                }
+            },
+        }
+        prev = line.chars().last().unwrap_or(' ');
+    }
+    if !content.is_empty() && !content.ends_with('\n') {
+        lines.push(line);
+    }
+    lines
+}
+
+
+/// Finds lines matching the user provided line exclusion patterns
+fn find_excluded_lines(content: &str, config: &Config, analysis: &mut LineAnalysis) {
     let lines = content.lines()
                        .enumerate()
-                       .filter(|&(_, x)| !x.chars().any(|x| !"(){}[]?;\t ,".contains(x)))
+                       .filter(|&(_, x)| config.exclude_line(x))
                        .map(|(i, _)| i+1)
                        .collect::<Vec<usize>>();
     analysis.add_to_ignore(&lines);
 }
 
 
+/// Comments marking the start and end of a region excluded from coverage
+const SKIP_START: &str = "tarpaulin-skip-start";
+const SKIP_STOP: &str = "tarpaulin-skip-stop";
+
+/// Returns the marker in a line comment, if the line is one
+fn skip_marker(line: &str) -> Option<&str> {
+    let line = line.trim();
+    if line.starts_with("//") {
+        Some(line.trim_start_matches('/').trim())
+    } else {
+        None
+    }
+}
+
+/// Ignores every line between `// tarpaulin-skip-start` and
+/// `// tarpaulin-skip-stop` comments, markers included. Regions may be nested
+/// in which case the outermost region is skipped. An unterminated region is
+/// skipped to the end of the file.
+fn find_skip_regions(content: &str, path: &Path, analysis: &mut LineAnalysis) {
+    let mut depth = 0usize;
+    let mut start = 0;
+    let mut lines = vec![];
+    let mut last = 0;
+    for (i, line) in content.lines().enumerate() {
+        let line_no = i + 1;
+        last = line_no;
+        match skip_marker(line) {
+            Some(SKIP_START) => {
+                if depth == 0 {
+                    start = line_no;
+                }
+                depth += 1;
+            },
+            Some(SKIP_STOP) if depth == 0 => {
+                println!("Warning: {}:{} {} without a matching {}",
+                         path.display(), line_no, SKIP_STOP, SKIP_START);
+            },
+            Some(SKIP_STOP) => {
+                depth -= 1;
+                if depth == 0 {
+                    lines.extend(start..=line_no);
+                }
+            },
+            _ => {},
+        }
+    }
+    if depth > 0 {
+        println!("Warning: {}:{} {} is never stopped, skipping to the end of the file",
+                 path.display(), start, SKIP_START);
+        lines.extend(start..=last);
+    }
+    analysis.add_to_ignore(&lines);
+}
+
+
 fn process_items(items: &[Item], ctx: &Context, analysis: &mut LineAnalysis) -> SubResult {
     let mut res = SubResult::Ok;
     for item in items.iter() {
@@ -298,10 +745,19 @@ fn process_items(items: &[Item], ctx: &Context, analysis: &mut LineAnalysis) ->
             Item::Union(ref i) => {
                 analysis.ignore_span(i.span());
             },
+            // Declarations with no executable code, constants and statics are
+            // evaluated at compile time
+            Item::Type(ref i) => analysis.ignore_span(i.span()),
+            Item::Const(ref i) => analysis.ignore_span(i.span()),
+            Item::Static(ref i) => analysis.ignore_span(i.span()),
+            Item::ForeignMod(ref i) => analysis.ignore_span(i.span()),
             Item::Trait(ref i) => visit_trait(&i, analysis, ctx),
             Item::Impl(ref i) => visit_impl(&i, analysis, ctx),
             Item::Macro(ref i) => {
-                if let SubResult::Unreachable = visit_macro_call(&i.mac, ctx, analysis) {
+                if is_macro_def(&i.mac) && ctx.config.macro_coverage == MacroCoverage::Invocation {
+                    // Expanded lines are attributed to the invocation site
+                    analysis.ignore_span(i.span());
+                } else if let SubResult::Unreachable = visit_macro_call(&i.mac, ctx, analysis) {
                     res = SubResult::Unreachable;
                 }
             },
@@ -338,6 +794,10 @@ fn process_statements(stmts: &[Stmt], ctx: &Context, analysis: &mut LineAnalysis
 
 fn visit_mod(module: &ItemMod, analysis: &mut LineAnalysis, ctx: &Context) {
     analysis.ignore_span(module.mod_token.span());
+    if module.content.is_none() {
+        // A module declaration including any attributes on it
+        analysis.ignore_span(module.span());
+    }
     let mut check_insides = true;
     for attr in &module.attrs {
         if let Some(x) = attr.interpret_meta() {
@@ -415,6 +875,8 @@ fn visit_fn(func: &ItemFn, analysis: &mut LineAnalysis, ctx: &Context) {
         if ctx.config.ignore_tests || (ignored_attr && !ctx.config.run_ignored) {
             analysis.ignore_span(func.span());
         }
+    } else if is_placeholder(&func.block, ctx) {
+        analysis.ignore_span(func.span());
     } else {
         if is_inline {
             // We need to force cover!
@@ -438,6 +900,27 @@ fn visit_fn(func: &ItemFn, analysis: &mut LineAnalysis, ctx: &Context) {
 }
 
 
+/// Checks whether the body of a function is only a panic, such as a
+/// placeholder `unimplemented!()` or `todo!()`. These are intentionally
+/// uncovered so they're ignored unless panics are included.
+fn is_placeholder(block: &Block, ctx: &Context) -> bool {
+    if ctx.config.include_panics || block.stmts.len() != 1 {
+        return false;
+    }
+    let mac = match block.stmts[0] {
+        Stmt::Item(Item::Macro(ref i)) => &i.mac,
+        Stmt::Expr(Expr::Macro(ref e)) | Stmt::Semi(Expr::Macro(ref e), _) => &e.mac,
+        _ => return false,
+    };
+    match mac.path.segments.last() {
+        Some(End(ref name)) => {
+            ["panic", "unimplemented", "todo", "unreachable"].iter().any(|m| name.ident == m)
+        },
+        _ => false,
+    }
+}
+
+
 fn check_attr_list(attrs: &[Attribute], ctx: &Context) -> bool {
     let mut check_cover = true;
     for attr in attrs {
@@ -485,6 +968,17 @@ fn check_cfg_attr(attr: &Meta) -> bool {
             }
             ignore_span = skip_match;
         }
+    } else if id == "cfg" {
+        // Code under cfg(not(tarpaulin)) isn't compiled for coverage runs
+        if let Meta::List(ml) = attr {
+            if let Some(NestedMeta::Meta(Meta::List(ref not))) = ml.nested.iter().next() {
+                ignore_span = ml.nested.len() == 1 && not.ident == "not" &&
+                    not.nested.iter().any(|n| match n {
+                        NestedMeta::Meta(Meta::Word(ref i)) => i == "tarpaulin",
+                        _ => false,
+                    });
+            }
+        }
     }
     ignore_span
 }
@@ -496,7 +990,9 @@ fn visit_trait(trait_item: &ItemTrait, analysis: &mut LineAnalysis, ctx: &Contex
         for item in &trait_item.items {
             if let TraitItem::Method(ref i) = *item {
                 if check_attr_list(&i.attrs, ctx) {
-                    if let Some(ref block) = i.default {
+                    if i.default.as_ref().map_or(false, |b| is_placeholder(b, ctx)) {
+                        analysis.ignore_span(i.span());
+                    } else if let Some(ref block) = i.default {
                         analysis.cover_span(item.span(), Some(ctx.file_contents));
                         visit_generics(&i.sig.decl.generics, analysis);
                         analysis.ignore.remove(&Lines::Line(i.sig.span().start().line));
@@ -506,6 +1002,9 @@ fn visit_trait(trait_item: &ItemTrait, analysis: &mut LineAnalysis, ctx: &Contex
                         let stmts_start = block.span().start().line;
                         let lines = (decl_start..(stmts_start+1)).collect::<Vec<_>>();
                         analysis.add_to_ignore(&lines);
+                    } else {
+                        // Required methods are only a signature
+                        analysis.ignore_span(i.span());
                     }
                 } else {
                     analysis.ignore_span(i.span());
@@ -513,6 +1012,12 @@ fn visit_trait(trait_item: &ItemTrait, analysis: &mut LineAnalysis, ctx: &Contex
                 for a in &i.attrs {
                     analysis.ignore_span(a.span());
                 }
+            } else {
+                match *item {
+                    // Associated types and consts are declarations
+                    TraitItem::Const(_) | TraitItem::Type(_) => analysis.ignore_span(item.span()),
+                    _ => {},
+                }
             }
         }
         visit_generics(&trait_item.generics, analysis);
@@ -527,7 +1032,9 @@ fn visit_impl(impl_blk: &ItemImpl, analysis: &mut LineAnalysis, ctx: &Context) {
     if check_cover {
         for item in &impl_blk.items {
             if let ImplItem::Method(ref i) = *item {
-                if check_attr_list(&i.attrs, ctx) {
+                if is_placeholder(&i.block, ctx) {
+                    analysis.ignore_span(i.span());
+                } else if check_attr_list(&i.attrs, ctx) {
                     analysis.cover_span(i.span(), Some(ctx.file_contents));
                     if let SubResult::Unreachable = process_statements(&i.block.stmts, ctx, analysis) {
                         // if the body of this method is unreachable, this means that the method
@@ -550,6 +1057,11 @@ fn visit_impl(impl_blk: &ItemImpl, analysis: &mut LineAnalysis, ctx: &Context) {
                 for a in &i.attrs {
                     analysis.ignore_span(a.span());
                 }
+            } else {
+                match *item {
+                    ImplItem::Const(_) | ImplItem::Type(_) => analysis.ignore_span(item.span()),
+                    _ => {},
+                }
             }
         }
         visit_generics(&impl_blk.generics, analysis);
@@ -771,6 +1283,14 @@ fn visit_struct_expr(structure: &ExprStruct, analysis: &mut LineAnalysis) -> Sub
 }
 
 
+fn is_macro_def(mac: &Macro) -> bool {
+    match mac.path.segments.last() {
+        Some(End(ref name)) => name.ident == "macro_rules",
+        _ => false,
+    }
+}
+
+
 fn visit_macro_call(mac: &Macro, ctx: &Context, analysis: &mut LineAnalysis) -> SubResult {
     let mut skip = false;
     let start = mac.span().start().line + 1;
@@ -779,9 +1299,16 @@ fn visit_macro_call(mac: &Macro, ctx: &Context, analysis: &mut LineAnalysis) ->
         let unreachable = name.ident == "unreachable";
         let standard_ignores =  name.ident == "unimplemented" || name.ident == "include";
         let ignore_panic =  ctx.config.ignore_panics && name.ident == "panic";
-        if standard_ignores || ignore_panic || unreachable {
+        // Global assembly isn't part of any function so never maps to a line
+        let global_asm = name.ident == "global_asm";
+        if standard_ignores || ignore_panic || unreachable || global_asm {
             analysis.ignore_span(mac.span());
             skip = true;
+        } else if name.ident == "asm" || name.ident == "llvm_asm" {
+            // Inline assembly is attributed to the line of the invocation, the
+            // template and operand lines below it are never traced
+            analysis.add_to_ignore(&(start..end).collect::<Vec<_>>());
+            skip = true;
         }
         if unreachable {
             return SubResult::Unreachable
@@ -819,6 +1346,7 @@ fn process_mac_args(tokens: &TokenStream) -> HashSet<usize> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::fs;
     use syn::parse_file;
 
     #[test]
@@ -1384,6 +1912,32 @@ mod tests {
     }
 
 
+    #[test]
+    fn not_tarpaulin_cfg() {
+        let config = Config::default();
+        let mut lines = LineAnalysis::new();
+        let ctx = Context {
+            config: &config,
+            file_contents: "#[cfg(not(tarpaulin))]
+                fn slow_init() {
+                    println!(\"Hello world\");
+                }
+
+            #[cfg(not(test))]
+            fn covered() {
+                println!(\"hell world\");
+            }
+            ",
+            file: Path::new(""),
+            ignore_mods: RefCell::new(HashSet::new()),
+        };
+        let parser = parse_file(ctx.file_contents).unwrap();
+        process_items(&parser.items, &ctx, &mut lines);
+        assert!(lines.ignore.contains(&Lines::Line(2)));
+        assert!(lines.ignore.contains(&Lines::Line(3)));
+        assert!(!lines.ignore.contains(&Lines::Line(8)));
+    }
+
     #[test]
     fn tarpaulin_skip_attr() {
         let config = Config::default();
@@ -1772,4 +2326,247 @@ mod tests {
         assert!(lines.ignore.contains(&Lines::Line(7)));
     }
 
+    #[test]
+    fn macro_definition_coverage() {
+        let mut config = Config::default();
+        let mut lines = LineAnalysis::new();
+        let ctx = Context {
+            config: &config,
+            file_contents: "macro_rules! add {
+                ($x:expr, $y:expr) => {
+                    $x + $y
+                };
+            }",
+            file: Path::new(""),
+            ignore_mods: RefCell::new(HashSet::new()),
+        };
+        let parser = parse_file(ctx.file_contents).unwrap();
+        process_items(&parser.items, &ctx, &mut lines);
+        assert!(lines.ignore.contains(&Lines::Line(3)));
+
+        config.macro_coverage = MacroCoverage::Def;
+        let mut lines = LineAnalysis::new();
+        let ctx = Context {
+            config: &config,
+            file_contents: "macro_rules! add {
+                ($x:expr, $y:expr) => {
+                    $x + $y
+                };
+            }",
+            file: Path::new(""),
+            ignore_mods: RefCell::new(HashSet::new()),
+        };
+        let parser = parse_file(ctx.file_contents).unwrap();
+        process_items(&parser.items, &ctx, &mut lines);
+        assert!(!lines.ignore.contains(&Lines::Line(3)));
+    }
+
+
+    #[test]
+    fn filter_declarations() {
+        let config = Config::default();
+        let mut lines = LineAnalysis::new();
+        let ctx = Context {
+            config: &config,
+            file_contents: "use std::fs;\nmod foo;\ntype Foo = u32;\nconst X: u32 = 4;\nstatic Y: u32 = 5;\ntrait T {\ntype A;\nconst B: u32;\nfn c(&self);\n}\nimpl T for u32 {\ntype A = u32;\nconst B: u32 = 1;\nfn c(&self) {\nprintln!(\"hi\");\n}\n}",
+            file: Path::new(""),
+            ignore_mods: RefCell::new(HashSet::new()),
+        };
+        let parser = parse_file(ctx.file_contents).unwrap();
+        process_items(&parser.items, &ctx, &mut lines);
+        for l in &[1, 2, 3, 4, 5, 7, 8, 9, 12, 13] {
+            assert!(lines.ignore.contains(&Lines::Line(*l)));
+        }
+        assert!(!lines.ignore.contains(&Lines::Line(15)));
+    }
+
+    #[test]
+    fn const_evaluated_fns() {
+        let content = "const fn square(x: u32) -> u32 {\n    x * x\n}\nconst SIZE: u32 = square(4);\n\
+                       const fn double(x: u32) -> u32 {\n    x * 2\n}\nfn run() -> u32 {\n    double(SIZE)\n}\n";
+        let parser = parse_file(content).unwrap();
+        let mut consts = ConstEvaluation::default();
+        consts.add_file(Path::new("lib.rs"), &parser.items, content);
+        let mut result = HashMap::new();
+        consts.apply(&mut result);
+        let analysis = &result[Path::new("lib.rs")];
+        assert!(analysis.should_ignore(2));
+        assert!(analysis.const_evaluated.contains(&2));
+        assert!(!analysis.should_ignore(6));
+        assert!(!analysis.should_ignore(9));
+    }
+
+    #[test]
+    fn const_fns_used_at_runtime() {
+        let content = "const fn triple(x: u32) -> u32 {\n    x * 3\n}\n\
+                       const fn inner(x: u32) -> u32 {\n    x + 1\n}\n\
+                       const fn outer(x: u32) -> u32 {\n    inner(x)\n}\n\
+                       const fn noted(x: u32) -> u32 {\n    x\n}\n\
+                       const A: u32 = triple(1) + outer(1) + noted(1);\n\
+                       fn run(v: Vec<u32>) -> Vec<u32> {\n    // noted(2)\n    println!(\"noted(3)\");\n    \
+                       v.into_iter().map(triple).map(outer).collect()\n}\n";
+        let parser = parse_file(content).unwrap();
+        let mut consts = ConstEvaluation::default();
+        consts.add_file(Path::new("lib.rs"), &parser.items, content);
+        let mut result = HashMap::new();
+        consts.apply(&mut result);
+        let analysis = &result[Path::new("lib.rs")];
+        // Passed to map so ran by the tests
+        assert!(!analysis.should_ignore(2));
+        // Used by outer which is ran by the tests
+        assert!(!analysis.should_ignore(5));
+        assert!(!analysis.should_ignore(8));
+        // Only named at runtime in a comment and a string
+        assert!(analysis.should_ignore(11));
+        assert!(analysis.const_evaluated.contains(&11));
+    }
+
+    #[test]
+    fn const_fns_by_path() {
+        let content = "mod a {\n    pub const fn new() -> u32 {\n        1\n    }\n}\n\
+                       mod b {\n    pub const fn new() -> u32 {\n        2\n    }\n}\n\
+                       const X: u32 = a::new() + b::new();\n\
+                       fn run() -> u32 {\n    b::new()\n}\n";
+        let parser = parse_file(content).unwrap();
+        let mut consts = ConstEvaluation::default();
+        consts.add_file(Path::new("lib.rs"), &parser.items, content);
+        let mut result = HashMap::new();
+        consts.apply(&mut result);
+        let analysis = &result[Path::new("lib.rs")];
+        assert!(analysis.should_ignore(3));
+        assert!(!analysis.should_ignore(8));
+    }
+
+    #[test]
+    fn filter_placeholder_fns() {
+        let mut config = Config::default();
+        let mut lines = LineAnalysis::new();
+        let ctx = Context {
+            config: &config,
+            file_contents: "fn foo() {\n    unimplemented!()\n}\nstruct A;\nimpl A {\n    fn bar(&self) {\n        todo!();\n    }\n}\n",
+            file: Path::new(""),
+            ignore_mods: RefCell::new(HashSet::new()),
+        };
+        let parser = parse_file(ctx.file_contents).unwrap();
+        process_items(&parser.items, &ctx, &mut lines);
+        assert!(lines.ignore.contains(&Lines::Line(1)));
+        assert!(lines.ignore.contains(&Lines::Line(6)));
+
+        config.include_panics = true;
+        let mut lines = LineAnalysis::new();
+        let ctx = Context {
+            config: &config,
+            file_contents: "fn foo() {\n    unimplemented!()\n}\n",
+            file: Path::new(""),
+            ignore_mods: RefCell::new(HashSet::new()),
+        };
+        let parser = parse_file(ctx.file_contents).unwrap();
+        process_items(&parser.items, &ctx, &mut lines);
+        assert!(!lines.ignore.contains(&Lines::Line(1)));
+    }
+
+    #[test]
+    fn filter_asm() {
+        let config = Config::default();
+        let mut lines = LineAnalysis::new();
+        let ctx = Context {
+            config: &config,
+            file_contents: "fn nop() {\n    unsafe {\n        asm!(\n            \"nop\",\n            \"nop\"\n        );\n    }\n}\nglobal_asm!(\n    \"nop\"\n);\n",
+            file: Path::new(""),
+            ignore_mods: RefCell::new(HashSet::new()),
+        };
+        let parser = parse_file(ctx.file_contents).unwrap();
+        process_items(&parser.items, &ctx, &mut lines);
+        assert!(!lines.ignore.contains(&Lines::Line(3)));
+        for l in &[4, 5, 6, 9, 10, 11] {
+            assert!(lines.ignore.contains(&Lines::Line(*l)));
+        }
+    }
+
+    #[test]
+    fn filter_skip_regions() {
+        let content = "fn log() {\n    // tarpaulin-skip-start\n    println!(\"a\");\n    // tarpaulin-skip-start\n    println!(\"b\");\n    // tarpaulin-skip-stop\n    println!(\"c\");\n    // tarpaulin-skip-stop\n    println!(\"d\");\n}\n";
+        let mut lines = LineAnalysis::new();
+        find_skip_regions(content, Path::new("lib.rs"), &mut lines);
+        for l in 2..9 {
+            assert!(lines.should_ignore(l));
+        }
+        assert!(!lines.should_ignore(1));
+        assert!(!lines.should_ignore(9));
+
+        let content = "fn log() {\n    // tarpaulin-skip-stop\n    println!(\"a\");\n    //tarpaulin-skip-start\n    println!(\"b\");\n}\n";
+        let mut lines = LineAnalysis::new();
+        find_skip_regions(content, Path::new("lib.rs"), &mut lines);
+        assert!(!lines.should_ignore(3));
+        for l in 4..7 {
+            assert!(lines.should_ignore(l));
+        }
+    }
+
+    #[test]
+    fn filter_closing_braces() {
+        let content = "fn foo(x: u32) -> u32 {\n    if x > 0 {\n        x\n    } // positive\n    else {\n        0\n    };\n    let v = vec![\n        1,\n    ],\n    x // result\n}\n";
+        let mut lines = LineAnalysis::new();
+        find_ignorable_lines(content, &mut lines);
+        for &l in &[4, 7, 10, 12] {
+            assert!(lines.should_ignore(l));
+        }
+        for &l in &[1, 2, 3, 5, 6, 8, 9, 11] {
+            assert!(!lines.should_ignore(l));
+        }
+    }
+
+    #[test]
+    fn comments_in_literals() {
+        let content = "fn urls() {\n    (\"//\", x)\n    (r#\"//\"#)\n    ('/', '\\'') // quotes\n    \"text\n    // in a string\n    }\"\n    (&'a x) // lifetime\n} // end\n";
+        let lines = strip_line_comments(content);
+        assert_eq!(lines[1], "    (\"//\", x)");
+        assert_eq!(lines[2], "    (r#\"//\"#)");
+        assert_eq!(lines[3], "    ('/', '\\'') ");
+        assert_eq!(lines[5], "    // in a string");
+        assert_eq!(lines[7], "    (&'a x) ");
+        assert_eq!(lines[8], "} ");
+        assert_eq!(lines.len(), 9);
+        let mut analysis = LineAnalysis::new();
+        find_ignorable_lines("    (\"//\")\n    } // end\n", &mut analysis);
+        assert!(!analysis.should_ignore(1));
+        assert!(analysis.should_ignore(2));
+    }
+
+
+    #[test]
+    fn should_panic_tests() {
+        let contents = "#[test]\n#[should_panic]\nfn aborts() {\n    panic!();\n}\n#[test]\nfn passes() {\n}\n\
+                        mod a {\n    mod tests {\n        #[test]\n        #[should_panic]\n        fn works() {}\n    }\n}\n\
+                        mod b {\n    #[test]\n    fn works() {}\n}\n";
+        let parser = parse_file(contents).unwrap();
+        let mut tests = HashSet::new();
+        find_should_panic(&parser.items, &mut vec!["api".to_string()], &mut tests);
+        assert!(tests.contains("api::aborts"));
+        assert!(tests.contains("api::a::tests::works"));
+        assert!(!tests.contains("api::passes"));
+        assert!(!tests.contains("api::b::works"));
+        assert_eq!(tests.len(), 2);
+    }
+
+    #[test]
+    fn module_paths() {
+        let root = ::std::env::temp_dir().join("tarpaulin-module-paths");
+        fs::create_dir_all(root.join("tests/api")).unwrap();
+        fs::create_dir_all(root.join("src/bin/tool")).unwrap();
+        fs::write(root.join("Cargo.toml"), "").unwrap();
+        fs::write(root.join("tests/api/main.rs"), "").unwrap();
+        fs::write(root.join("src/bin/tool/main.rs"), "").unwrap();
+        let path = |p: &str| file_module_path(&root.join(p));
+        assert!(path("src/lib.rs").is_empty());
+        assert!(path("src/main.rs").is_empty());
+        assert_eq!(path("src/report/mod.rs"), vec!["report"]);
+        assert_eq!(path("src/report/json.rs"), vec!["report", "json"]);
+        assert!(path("src/bin/tool.rs").is_empty());
+        assert_eq!(path("src/bin/tool/args.rs"), vec!["args"]);
+        assert!(path("tests/basic.rs").is_empty());
+        assert!(path("tests/api/main.rs").is_empty());
+        assert_eq!(path("tests/api/client.rs"), vec!["client"]);
+        assert_eq!(path("tests/common/mod.rs"), vec!["common"]);
+    }
 }
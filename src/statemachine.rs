@@ -1,8 +1,10 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fs::read_link;
+use std::path::Path;
 use std::time::Instant;
 use nix::Error as NixErr;
 use nix::sys::wait::*;
-use nix::sys::signal::Signal;
+use nix::sys::signal::{kill, Signal};
 use nix::errno::Errno;
 use nix::Result;
 use nix::unistd::Pid;
@@ -10,6 +12,8 @@ use breakpoint::*;
 use traces::*;
 use ptrace_control::*;
 use config::Config;
+use emit::HitEmitter;
+use test_loader::TracerError;
 
 
 
@@ -19,7 +23,9 @@ pub enum TestState {
     Start {
         start_time: Instant,
     },
-    /// Initialise: once test process appears instrument
+    /// Initialise: once test process appears instrument. The test is stopped
+    /// at the exec trap so nothing, not even the constructors ran before main,
+    /// has executed yet
     Initialise ,
     /// Waiting for breakpoint to be hit or test to end
     Waiting {
@@ -130,15 +136,31 @@ impl TestState {
 }
 
 
+/// Generates the traces for a binary exec'd by the test, returns None if the
+/// binary shouldn't be traced.
+pub type ExecTracer<'a> = Box<Fn(&Path) -> Option<TraceMap> + 'a>;
+
+
 pub fn create_state_machine<'a>(test: Pid,
                                 traces: &'a mut TraceMap,
-                                config: &'a Config) -> (TestState, LinuxData<'a>) {
+                                config: &'a Config,
+                                exec_tracer: Option<ExecTracer<'a>>) -> (TestState, LinuxData<'a>) {
     let mut data = LinuxData::new(traces, config);
     data.parent = test;
+    data.exec_tracer = exec_tracer;
     (TestState::start_state(), data)
 }
 
 
+/// Coverage state of a binary exec'd by the test and followed by tarpaulin
+struct FollowedExec {
+    /// Instrumentation points of the exec'd binary
+    traces: TraceMap,
+    /// Map of addresses to breakpoints in the exec'd binary
+    breakpoints: HashMap<u64, Breakpoint>,
+}
+
+
 /// Handle to linux process state
 pub struct LinuxData<'a> {
     /// Recent result from waitpid to be handled by statemachine
@@ -155,16 +177,66 @@ pub struct LinuxData<'a> {
     config: &'a Config,
     /// Used to store error for user in the event something goes wrong
     pub error_message: Option<String>,
+    /// Signal the test aborted with, such as a panic with `panic=abort`
+    pub abort_signal: Option<Signal>,
+    /// Exit code of the child if it exited before the exec trap, so the test
+    /// was never ran
+    pub exited_before_exec: Option<i32>,
     /// Thread count. Hopefully getting rid of in future
     thread_count: isize,
     /// Used to show anomalies noticed so hit counts disabled
-    force_disable_hit_count: bool
+    force_disable_hit_count: bool,
+    /// Creates traces for binaries exec'd by the test that should be followed
+    exec_tracer: Option<ExecTracer<'a>>,
+    /// Keep tracing exec'd binaries the exec tracer doesn't instrument so the
+    /// binaries they exec in turn can be followed
+    pub follow_untraced: bool,
+    /// Pass the signals the traced processes get on to them
+    pub forward_signals: bool,
+    /// Exec'd processes being traced mapped to their coverage state
+    followed: HashMap<Pid, FollowedExec>,
+    /// Threads and forks of followed processes mapped to the followed process
+    followed_children: HashMap<Pid, Pid>,
+    /// Coverage of followed processes which have since exec'd again
+    followed_traces: TraceMap,
+    /// Processes forked by the test, these don't count towards the threads
+    forks: HashSet<Pid>,
+    /// Socket line hits are streamed to
+    emitter: Option<HitEmitter>,
+    /// Exit code of the test and when it exited if its forks are still being
+    /// traced during the post test delay
+    exited: Option<(i32, Instant)>,
+}
+
+
+/// Kills a traced process and waits for each of its threads to exit
+fn kill_and_reap(pid: Pid) {
+    let threads = process_threads(pid);
+    let _ = kill(pid, Signal::SIGKILL);
+    for tid in threads {
+        loop {
+            match waitpid(tid, Some(WaitPidFlag::__WALL)) {
+                // Exit events are still reported for killed tracees
+                Ok(WaitStatus::PtraceEvent(..)) | Ok(WaitStatus::Stopped(..)) => {
+                    let _ = continue_exec(tid, None);
+                },
+                Err(NixErr::Sys(Errno::EINTR)) => {},
+                _ => break,
+            }
+        }
+    }
 }
 
 
 impl <'a> StateData for LinuxData<'a> {
 
     fn start(&mut self) -> Option<TestState> {
+        if self.config.attach.is_some() {
+            // Every thread was stopped and set up when attaching
+            self.current = self.parent;
+            self.wait = WaitStatus::Stopped(self.parent, Signal::SIGSTOP);
+            return Some(TestState::Initialise);
+        }
         match waitpid(self.current, Some(WaitPidFlag::WNOHANG)) {
             Ok(WaitStatus::StillAlive) => None,
             Ok(sig @ WaitStatus::Stopped(_, Signal::SIGTRAP)) => {
@@ -174,10 +246,17 @@ impl <'a> StateData for LinuxData<'a> {
                 self.wait = sig;
                 Some(TestState::Initialise)
             },
+            Ok(WaitStatus::Exited(_, ec)) => {
+                // Child exited before the exec trap, it never ran the test
+                self.exited_before_exec = Some(ec);
+                Some(TestState::End(ec))
+            },
             Ok(_) => {
                 println!("Unexpected signal when starting test");
                 None
             },
+            // Interrupted by a signal, retry on the next step
+            Err(NixErr::Sys(Errno::EINTR)) => None,
             Err(e) => {
                 println!("Error when starting test: {}", e);
                 Some(TestState::Unrecoverable)
@@ -197,6 +276,11 @@ impl <'a> StateData for LinuxData<'a> {
                     Ok(bp) => {
                         let _ = self.breakpoints.insert(addr, bp);
                     },
+                    Err(NixErr::Sys(Errno::EPERM)) => {
+                        self.error_message = Some(TracerError::PtraceDenied.to_string());
+                        instrumented = false;
+                        break;
+                    },
                     Err(e) if e==NixErr::Sys(Errno::EIO) => {
                         println!("ERROR: Tarpaulin cannot find code addresses \
                                   check that pie is disabled for your linker. \
@@ -218,16 +302,35 @@ impl <'a> StateData for LinuxData<'a> {
         }
         if !instrumented {
             TestState::Abort
-        }
-        else if continue_exec(self.parent, None).is_ok() {
-            TestState::wait_state()
         } else {
-            TestState::Unrecoverable
+            // Only continue once every breakpoint is in place, otherwise code
+            // ran during startup such as #[ctor] functions is missed. An
+            // attached process has all its threads stopped.
+            let threads = if self.config.attach.is_some() {
+                process_threads(self.parent)
+            } else {
+                vec![self.parent]
+            };
+            self.thread_count += threads.len() as isize - 1;
+            match threads.iter().map(|&t| continue_exec(t, None)).collect::<Result<Vec<_>>>() {
+                Ok(_) => TestState::wait_state(),
+                Err(NixErr::Sys(Errno::EPERM)) => {
+                    self.error_message = Some(TracerError::PtraceDenied.to_string());
+                    TestState::Abort
+                },
+                Err(_) => TestState::Unrecoverable,
+            }
         }
     }
 
 
     fn wait(&mut self) -> Option<TestState> {
+        if let Some((ec, exit_time)) = self.exited {
+            let delay = self.config.post_test_delay.unwrap_or_default();
+            if self.forks.is_empty() || exit_time.elapsed() >= delay {
+                return Some(self.end(ec));
+            }
+        }
         let wait = waitpid(Pid::from_raw(-1), Some(WaitPidFlag::WNOHANG | WaitPidFlag::__WALL));
         match wait {
             Ok(WaitStatus::StillAlive) => {
@@ -238,6 +341,14 @@ impl <'a> StateData for LinuxData<'a> {
                 self.wait = s;
                 Some(TestState::Stopped)
             },
+            Err(NixErr::Sys(Errno::EINTR)) => {
+                self.wait = WaitStatus::StillAlive;
+                None
+            },
+            Err(NixErr::Sys(Errno::ECHILD)) if self.exited.is_some() => {
+                // The forks of the test all exited
+                self.exited.map(|(ec, _)| TestState::End(ec))
+            },
             Err(_) => {
                 self.error_message = Some("An error occurred while waiting for response from test".to_string());
                 Some(TestState::Unrecoverable)
@@ -276,12 +387,20 @@ impl <'a> StateData for LinuxData<'a> {
                     TestState::Unrecoverable
                 }
             },
+            WaitStatus::Stopped(c, s @ Signal::SIGABRT) |
+            WaitStatus::Stopped(c, s @ Signal::SIGILL) => {
+                // Likely a panic with panic=abort. Always forward so the test
+                // terminates as it would outside of tarpaulin, the hits
+                // collected so far are kept.
+                let _ = continue_exec(c, Some(s));
+                TestState::wait_state()
+            },
             WaitStatus::Stopped(_, Signal::SIGSEGV) => {
                 self.error_message = Some("Error a segfault occured when executing test".to_string());
                 TestState::Unrecoverable
             },
             WaitStatus::Stopped(c, s) => {
-                let sig = if self.config.forward_signals {
+                let sig = if self.forward_signals {
                     Some(s)
                 } else {
                     None
@@ -302,7 +421,13 @@ impl <'a> StateData for LinuxData<'a> {
                     value.thread_killed(child);
                 }
                 if child == self.parent {
-                    TestState::End(ec)
+                    if self.config.post_test_delay.is_some() && !self.forks.is_empty() {
+                        // Keep tracing the processes the test forked for a while
+                        self.exited = Some((ec, Instant::now()));
+                        TestState::wait_state()
+                    } else {
+                        self.end(ec)
+                    }
                 } else {
                     // Process may have already been destroyed. This is just incase
                     let _ = continue_exec(self.parent, None);
@@ -315,6 +440,9 @@ impl <'a> StateData for LinuxData<'a> {
 
 
     fn cleanup(&mut self)  {
+        for bp in self.breakpoints.values_mut() {
+            bp.restore();
+        }
         if let Some(ref e) = self.error_message {
             println!("An error occurred during run. Coverage results may be inaccurate.");
             println!("{}", e);
@@ -333,9 +461,81 @@ impl <'a>LinuxData<'a> {
             traces,
             config,
             error_message:None,
+            abort_signal: None,
+            exited_before_exec: None,
             thread_count: 0,
-            force_disable_hit_count: config.count
+            force_disable_hit_count: config.count,
+            exec_tracer: None,
+            follow_untraced: config.follow_exec,
+            forward_signals: config.forward_signals,
+            followed: HashMap::new(),
+            followed_children: HashMap::new(),
+            followed_traces: TraceMap::new(),
+            forks: HashSet::new(),
+            exited: None,
+            emitter: config.emit_socket.as_ref().and_then(|addr| {
+                match HitEmitter::connect(addr) {
+                    Ok(e) => Some(e),
+                    Err(e) => {
+                        println!("Failed to connect to {}: {}", addr, e);
+                        None
+                    },
+                }
+            }),
+        }
+    }
+
+    /// Takes the coverage collected from binaries exec'd by the test
+    pub fn take_followed_traces(&mut self) -> TraceMap {
+        let mut result = TraceMap::new();
+        ::std::mem::swap(&mut result, &mut self.followed_traces);
+        for (_, f) in self.followed.drain() {
+            result.merge(&f.traces);
         }
+        result
+    }
+
+    /// Returns the followed process a pid belongs to if there is one
+    fn followed_owner(&self, pid: Pid) -> Option<Pid> {
+        if self.followed.contains_key(&pid) {
+            Some(pid)
+        } else {
+            self.followed_children.get(&pid).cloned()
+        }
+    }
+
+    /// Instruments a newly exec'd binary if the exec tracer accepts it.
+    /// Otherwise the process is still traced when following execs so any
+    /// binaries it execs can be followed, else it's detached.
+    fn follow_exec(&mut self, child: Pid) -> Result<TestState> {
+        let traces = match (read_link(format!("/proc/{}/exe", child)), &self.exec_tracer) {
+            (Ok(exe), &Some(ref tracer)) => tracer(&exe),
+            _ => None,
+        };
+        self.followed_children.remove(&child);
+        if let Some(previous) = self.followed.remove(&child) {
+            self.followed_traces.merge(&previous.traces);
+        }
+        if let Some(traces) = traces {
+            let mut breakpoints = HashMap::new();
+            for trace in traces.all_traces() {
+                if let Some(addr) = trace.address {
+                    if let Ok(bp) = Breakpoint::new(child, addr) {
+                        breakpoints.insert(addr, bp);
+                    }
+                }
+            }
+            self.followed.insert(child, FollowedExec {
+                traces,
+                breakpoints,
+            });
+        } else if !self.follow_untraced {
+            self.forks.remove(&child);
+            detach_child(child)?;
+            return Ok(TestState::wait_state());
+        }
+        continue_exec(child, None)?;
+        Ok(TestState::wait_state())
     }
 
     fn handle_ptrace_event(&mut self, child: Pid, sig: Signal, event: i32) -> Result<TestState> {
@@ -344,8 +544,11 @@ impl <'a>LinuxData<'a> {
         if sig == Signal::SIGTRAP {
             match event {
                 PTRACE_EVENT_CLONE => {
-                    if get_event_data(child).is_ok() {
+                    if let Ok(new_child) = get_event_data(child) {
                         self.thread_count += 1;
+                        if let Some(owner) = self.followed_owner(child) {
+                            self.followed_children.insert(Pid::from_raw(new_child as pid_t), owner);
+                        }
                         continue_exec(child, None)?;
                         Ok(TestState::wait_state())
                     } else {
@@ -354,15 +557,30 @@ impl <'a>LinuxData<'a> {
                     }
                 },
                 PTRACE_EVENT_FORK | PTRACE_EVENT_VFORK => {
+                    // Forks share the memory layout and breakpoints so their
+                    // hits are recorded against the process they forked from
+                    if let Ok(new_child) = get_event_data(child) {
+                        let new_child = Pid::from_raw(new_child as pid_t);
+                        self.forks.insert(new_child);
+                        if let Some(owner) = self.followed_owner(child) {
+                            self.followed_children.insert(new_child, owner);
+                        }
+                    }
                     continue_exec(child, None)?;
                     Ok(TestState::wait_state())
                 },
                 PTRACE_EVENT_EXEC => {
-                    detach_child(child)?;
-                    Ok(TestState::wait_state())
+                    if self.exec_tracer.is_some() {
+                        self.follow_exec(child)
+                    } else {
+                        detach_child(child)?;
+                        Ok(TestState::wait_state())
+                    }
                 },
                 PTRACE_EVENT_EXIT => {
-                    self.thread_count -= 1;
+                    if !self.forks.remove(&child) {
+                        self.thread_count -= 1;
+                    }
                     continue_exec(child, None)?;
                     Ok(TestState::wait_state())
                 },
@@ -377,8 +595,17 @@ impl <'a>LinuxData<'a> {
     fn collect_coverage_data(&mut self) -> Result<TestState> {
         if let Ok(rip) = current_instruction_pointer(self.current) {
             let rip = (rip - 1) as u64;
-            if  self.breakpoints.contains_key(&rip) {
-                let bp = &mut self.breakpoints.get_mut(&rip).unwrap();
+            let current = self.current;
+            let followed = match self.followed_owner(current) {
+                Some(owner) => self.followed.get_mut(&owner),
+                None => None,
+            };
+            let (breakpoints, traces) = match followed {
+                Some(f) => (&mut f.breakpoints, &mut f.traces),
+                None => (&mut self.breakpoints, &mut *self.traces),
+            };
+            if  breakpoints.contains_key(&rip) {
+                let bp = &mut breakpoints.get_mut(&rip).unwrap();
                 let enable = self.config.count && self.thread_count < 2;
                 if !enable && self.force_disable_hit_count {
                     println!("Code is mulithreaded, disabling hit count");
@@ -386,20 +613,33 @@ impl <'a>LinuxData<'a> {
                     self.force_disable_hit_count = false;
                 }
                 // Don't reenable if multithreaded as can't yet sort out segfault issue
-                let updated = if let Ok(x) = bp.process(self.current, enable) {
+                let updated = if let Ok(x) = bp.process(current, enable) {
                      x
                 } else {
                     // So failed to process a breakpoint.. Still continue to avoid
                     // stalling
-                    continue_exec(self.current, None)?;
+                    continue_exec(current, None)?;
                     false
                 };
                 if updated {
-                    if let Some(ref mut t) = self.traces.get_trace_mut(rip) {
+                    if let Some(ref mut t) = traces.get_trace_mut(rip) {
                         if let CoverageStat::Line(ref mut x) = t.stats {
                             *x += 1;
                         }
                     }
+                    let mut emit_failed = false;
+                    if let Some(ref mut emitter) = self.emitter {
+                        if let Some((path, line)) = traces.get_location(rip) {
+                            let path = self.config.strip_project_path(path);
+                            if let Err(e) = emitter.emit(&path, line) {
+                                println!("Failed to send line hit, no longer sending: {}", e);
+                                emit_failed = true;
+                            }
+                        }
+                    }
+                    if emit_failed {
+                        self.emitter = None;
+                    }
                 }
             } else {
                 continue_exec(self.current, None)?;
@@ -411,12 +651,55 @@ impl <'a>LinuxData<'a> {
     }
 
 
+    /// Ends the test with the given exit code. Forks still running are killed
+    /// and reaped along with their threads, otherwise they'd be left stopped
+    /// with breakpoints in place and their wait events picked up while tracing
+    /// the next test.
+    fn end(&mut self, ec: i32) -> TestState {
+        for fork in self.forks.drain() {
+            kill_and_reap(fork);
+        }
+        TestState::End(ec)
+    }
+
+    /// Kills the test along with its forks and the processes it exec'd that
+    /// are followed, reaping all of them so none are left behind stopped when
+    /// tarpaulin is interrupted
+    pub fn kill_all(&mut self) {
+        let followed = self.followed.keys().cloned().collect::<Vec<_>>();
+        for pid in self.forks.drain().chain(followed) {
+            kill_and_reap(pid);
+        }
+        kill_and_reap(self.parent);
+    }
+
     fn handle_signaled(&mut self) -> Result<TestState> {
         match self.wait {
             WaitStatus::Signaled(child, Signal::SIGTRAP, true) => {
                 continue_exec(child, None)?;
                 Ok(TestState::wait_state())
             },
+            WaitStatus::Signaled(child, sig @ Signal::SIGABRT, _) |
+            WaitStatus::Signaled(child, sig @ Signal::SIGILL, _) => {
+                if child == self.parent {
+                    // With panic=abort this may be the expected panic of a
+                    // #[should_panic] test, the traces are kept and whether
+                    // it failed is decided by the caller
+                    self.abort_signal = Some(sig);
+                    Ok(TestState::End(128 + sig as i32))
+                } else {
+                    Ok(TestState::wait_state())
+                }
+            },
+            WaitStatus::Signaled(child, _, _) if child != self.parent => {
+                // A forked test process was killed, the test framework handles
+                // its failure
+                for value in self.breakpoints.values_mut() {
+                    value.thread_killed(child);
+                }
+                self.forks.remove(&child);
+                Ok(TestState::wait_state())
+            },
             _ => {
                 self.error_message = Some("Unexpected stop".to_string());
                 Ok(TestState::Unrecoverable)
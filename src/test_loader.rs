@@ -1,20 +1,125 @@
-use std::io;
-use std::path::{PathBuf, Path};
+use std::{error, fmt, io};
+use std::path::{Component, PathBuf, Path};
 use std::fs::File;
-use std::collections::HashMap;
-use object::{Object, File as OFile};
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use object::{Object, File as OFile, SymbolKind};
 use memmap::MmapOptions;
 use gimli::*;
 use rustc_demangle::demangle;
-use cargo::core::Workspace;
+use serde_json::{self, Value};
+use cargo::core::{Package, Workspace};
 
-use config::Config;
+use config::{Config, Granularity};
 use source_analysis::*;
 use traces::*;
 
 
-/// Describes a function as `low_pc`, `high_pc` and bool representing `is_test`.
-type FuncDesc = (u64, u64, FunctionType);
+/// Describes a function as the address ranges it occupies, each a start
+/// address and length, the type of function and its demangled name. Most
+/// functions have a single range but optimised code may be split into several.
+type FuncDesc = (Vec<(u64, u64)>, FunctionType, String);
+
+/// Target of the tracer's log messages, enable them with
+/// `RUST_LOG=tarpaulin=debug`
+const LOG_TARGET: &str = "tarpaulin";
+
+/// Set once a test function is found in a binary ran with the test harness
+static TESTS_FOUND: AtomicBool = AtomicBool::new(false);
+
+/// Whether any of the test binaries traced so far had test functions. Test
+/// binaries without any likely have their tests behind a feature that wasn't
+/// enabled or in a module not recognised as tests.
+pub fn tests_found() -> bool {
+    TESTS_FOUND.load(Ordering::SeqCst)
+}
+
+/// Forgets the test functions found so far, so each run through the test
+/// binaries such as a watch rerun or feature set is checked on its own
+pub fn reset_tests_found() {
+    TESTS_FOUND.store(false, Ordering::SeqCst);
+}
+
+lazy_static! {
+    /// Tracer data of each traced executable recorded for `--dump-traces`
+    static ref TRACE_DUMP: Mutex<Vec<Value>> = Mutex::new(vec![]);
+}
+
+/// Writes the tracer data recorded for every executable traced to the given
+/// path as a JSON list, so classification issues can be reproduced without
+/// the executables.
+pub fn write_trace_dump(path: &Path) -> io::Result<()> {
+    let dump = TRACE_DUMP.lock().map(|d| d.clone()).unwrap_or_default();
+    let mut file = File::create(path)?;
+    serde_json::to_writer_pretty(&mut file, &dump)?;
+    file.write_all(b"\n")
+}
+
+/// Serializes the tracer data of every location ordered by path, line and
+/// address
+fn dump_tracer_data(data: &HashMap<SourceLocation, Vec<TracerData>>) -> Value {
+    let mut rows = data.iter()
+                       .flat_map(|(loc, v)| v.iter().map(move |d| (loc, d)))
+                       .collect::<Vec<_>>();
+    rows.sort_by_key(|&(loc, d)| (loc.path.clone(), loc.line, d.address));
+    Value::Array(rows.into_iter()
+                     .map(|(loc, d)| json!({
+                         "path": loc.path,
+                         "line": loc.line,
+                         "address": d.address,
+                         "length": d.length,
+                         "type": format!("{:?}", d.trace_type),
+                     }))
+                     .collect())
+}
+
+/// DWARF sections read when generating the trace map
+const DEBUG_SECTIONS: &[&str] = &[".debug_info", ".debug_abbrev", ".debug_str",
+                                   ".debug_line", ".debug_ranges", ".debug_rnglists"];
+
+/// DWARF sections copied out of the object file so the file mapping can be
+/// released as soon as they're read rather than for the whole run.
+struct DebugSections {
+    sections: HashMap<&'static str, Vec<u8>>,
+    /// Names of the function symbols by address, used to name functions when
+    /// their linkage name can't be read from `.debug_str`
+    symbols: HashMap<u64, String>,
+}
+
+impl DebugSections {
+    fn read(obj: &OFile) -> Self {
+        let sections = DEBUG_SECTIONS.iter()
+                                     .filter_map(|&name| {
+                                         obj.section_data_by_name(name)
+                                            .map(|data| (name, data.into_owned()))
+                                     })
+                                     .collect();
+        let symbols = obj.symbols()
+                         .filter(|s| s.kind() == SymbolKind::Text && s.address() != 0)
+                         .filter_map(|s| s.name().map(|n| (s.address(), n.to_string())))
+                         .collect();
+        DebugSections { sections, symbols }
+    }
+
+    /// Warns if functions can't be named from the debug info, without names
+    /// tests can't be told apart from other functions
+    fn check_names(&self) {
+        if self.section_data_by_name(".debug_str").map_or(true, |s| s.is_empty()) {
+            if self.symbols.is_empty() {
+                println!("Warning: the binary has no .debug_str or symbol table so tests can't be \
+                          detected, don't strip the test binary");
+            } else {
+                println!("Warning: the binary has no .debug_str, naming functions from the symbol table");
+            }
+        }
+    }
+
+    fn section_data_by_name(&self, name: &str) -> Option<&[u8]> {
+        self.sections.get(name).map(|data| data.as_slice())
+    }
+}
 
 #[derive(Clone, Copy, PartialEq)]
 enum FunctionType {
@@ -41,6 +146,72 @@ pub enum LineType {
     UnusedGeneric,
 }
 
+/// Errors that can occur while loading a test executable and tracing it
+#[derive(Debug)]
+pub enum TracerError {
+    /// The test executable has no DWARF debug information
+    NoDebugInfo,
+    /// The DWARF debug information couldn't be parsed
+    DwarfParse(gimli::Error),
+    /// Failed to open or map the test executable
+    Io(io::Error),
+    /// The test executable isn't an object file format tarpaulin can read
+    UnsupportedBinary,
+    /// The forked child was unable to exec the test executable
+    ExecFailed(PathBuf),
+    /// Tracing was refused, usually by a seccomp filter or the Yama ptrace
+    /// scope
+    PtraceDenied,
+    /// The trace plan couldn't be read or a trace point in it is malformed
+    TracePlan(String),
+}
+
+impl fmt::Display for TracerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TracerError::NoDebugInfo => {
+                write!(f, "test executable has no debug info, check debug is \
+                           enabled for the test profile")
+            },
+            TracerError::DwarfParse(ref e) => {
+                write!(f, "failed to parse the DWARF debug info: {}", e)
+            },
+            TracerError::Io(ref e) => {
+                write!(f, "failed to read test executable: {}", e)
+            },
+            TracerError::UnsupportedBinary => {
+                write!(f, "test executable isn't in a supported binary format")
+            },
+            TracerError::ExecFailed(ref p) => {
+                write!(f, "failed to exec test binary {}", p.display())
+            },
+            TracerError::PtraceDenied => {
+                write!(f, "ptrace isn't permitted so coverage can't be collected. In a sandbox \
+                           or container allow ptrace in the seccomp profile, with docker run \
+                           with --security-opt seccomp=unconfined, and check \
+                           /proc/sys/kernel/yama/ptrace_scope isn't 3")
+            },
+            TracerError::TracePlan(ref e) => {
+                write!(f, "invalid trace plan: {}", e)
+            },
+        }
+    }
+}
+
+impl error::Error for TracerError {}
+
+impl From<io::Error> for TracerError {
+    fn from(e: io::Error) -> TracerError {
+        TracerError::Io(e)
+    }
+}
+
+impl From<gimli::Error> for TracerError {
+    fn from(e: gimli::Error) -> TracerError {
+        TracerError::DwarfParse(e)
+    }
+}
+
 #[derive(Clone, PartialEq, Eq, Hash)]
 struct SourceLocation {
     pub path: PathBuf,
@@ -60,13 +231,20 @@ pub struct TracerData {
 
 
 fn generate_func_desc<R, Offset>(die: &DebuggingInformationEntry<R, Offset>,
-                                 debug_str: &DebugStr<R>) -> Result<FuncDesc>
+                                 header: &CompilationUnitHeader<R, Offset>,
+                                 debug_str: &DebugStr<R>,
+                                 range_lists: &RangeLists<R>,
+                                 symbols: &HashMap<u64, String>,
+                                 base_address: u64,
+                                 config: &Config) -> Result<FuncDesc>
     where R: Reader<Offset = Offset>,
           Offset: ReaderOffset
 {
     let mut func_type = FunctionType::Standard;
+    let mut func_name = String::new();
     let low = die.attr_value(DW_AT_low_pc)?;
     let high = die.attr_value(DW_AT_high_pc)?;
+    let ranges = die.attr_value(DW_AT_ranges)?;
     let linkage = die.attr_value(DW_AT_linkage_name)?;
 
     // Low is a program counter address so stored in an Addr
@@ -74,27 +252,60 @@ fn generate_func_desc<R, Offset>(die: &DebuggingInformationEntry<R, Offset>,
         Some(AttributeValue::Addr(x)) => x,
         _ => 0u64,
     };
-    // High is an offset from the base pc, therefore is u64 data.
+    // High is usually an offset from the base pc, therefore is u64 data. Older
+    // DWARF gives the address after the function instead.
     let high = match high {
         Some(AttributeValue::Udata(x)) => x,
+        Some(AttributeValue::Addr(x)) => x.checked_sub(low).unwrap_or(0),
         _ => 0u64,
     };
-    if let Some(AttributeValue::DebugStrRef(offset)) = linkage {
-        let name = debug_str.get_str(offset)
-            .and_then(|r| r.to_string().map(|s| s.to_string()))
-            .unwrap_or_else(|_| "".into());
-        let name = demangle(name.as_ref()).to_string();
+    // Non-contiguous functions list their extents in the range lists instead
+    let ranges = if let Some(AttributeValue::RangeListsRef(offset)) = ranges {
+        let mut result = vec![];
+        let mut iter = range_lists.ranges(offset,
+                                          header.version(),
+                                          header.address_size(),
+                                          base_address)?;
+        while let Some(range) = iter.next()? {
+            // Malformed ranges ending before they begin are dropped
+            if let Some(length) = range.end.checked_sub(range.begin) {
+                result.push((range.begin, length));
+            }
+        }
+        result
+    } else {
+        vec![(low, high)]
+    };
+    let name = match linkage {
+        Some(AttributeValue::DebugStrRef(offset)) => {
+            debug_str.get_str(offset)
+                     .and_then(|r| r.to_string().map(|s| s.to_string()))
+                     .ok()
+        },
+        Some(AttributeValue::String(r)) => r.to_string().map(|s| s.to_string()).ok(),
+        _ => None,
+    };
+    // Stripped or empty string sections leave the symbol table to name it
+    let name = name.filter(|n| !n.is_empty()).or_else(|| {
+        ranges.first()
+              .filter(|&&(address, _)| address != 0)
+              .and_then(|&(address, _)| symbols.get(&address).cloned())
+    });
+    if let Some(name) = name {
+        // Alternate formatting leaves off the symbol hash
+        let name = format!("{:#}", demangle(name.as_ref()));
         // Simplest test is whether it's in tests namespace.
         // Rust guidelines recommend all tests are in a tests module.
-        func_type = if name.contains("tests::") {
+        func_type = if config.is_test_function(&name) {
             FunctionType::Test
-        } else if name.contains("__test::main") {
+        } else if config.is_generated_main(&name) {
             FunctionType::Generated
         } else {
             FunctionType::Standard
         };
+        func_name = name;
     }
-    Ok((low, high, func_type))
+    Ok((ranges, func_type, func_name))
 }
 
 
@@ -102,53 +313,107 @@ fn generate_func_desc<R, Offset>(die: &DebuggingInformationEntry<R, Offset>,
 /// This will identify definite tests, but may be prone to false negatives.
 fn get_entry_points<R, Offset>(debug_info: &CompilationUnitHeader<R, Offset>,
                                debug_abbrev: &Abbreviations,
-                               debug_str: &DebugStr<R>) -> Vec<FuncDesc>
+                               debug_str: &DebugStr<R>,
+                               range_lists: &RangeLists<R>,
+                               symbols: &HashMap<u64, String>,
+                               config: &Config) -> Vec<FuncDesc>
     where R: Reader<Offset = Offset>,
           Offset: ReaderOffset
 {
     let mut result:Vec<FuncDesc> = Vec::new();
     let mut cursor = debug_info.entries(debug_abbrev);
-    // skip compilation unit root.
-    let _ = cursor.next_entry();
+    // Compilation unit root, its low_pc is the base for range lists.
+    let base_address = match cursor.next_dfs() {
+        Ok(Some((_, root))) => match root.attr_value(DW_AT_low_pc) {
+            Ok(Some(AttributeValue::Addr(x))) => x,
+            _ => 0u64,
+        },
+        _ => 0u64,
+    };
     while let Ok(Some((_, node))) = cursor.next_dfs() {
         // Function DIE
         if node.tag() == DW_TAG_subprogram {
-
-            if let Ok(fd) = generate_func_desc(node, debug_str) {
-                result.push(fd);
+            match generate_func_desc(node, debug_info, debug_str, range_lists, symbols, base_address, config) {
+                Ok(fd) => result.push(fd),
+                Err(e) => debug!(target: LOG_TARGET, "Skipping function at {:?}: {}", node.offset(), e),
             }
         }
     }
     result
 }
 
+/// Identical code folding can give several functions the same entry address,
+/// if any of them is a test the address is a test entry. Otherwise the first
+/// function found at the address is kept.
+fn fold_entries(entries: Vec<(u64, LineType)>) -> Vec<(u64, LineType)> {
+    let is_test = |t: LineType| match t {
+        LineType::TestEntry(_) => true,
+        _ => false,
+    };
+    let mut result: Vec<(u64, LineType)> = Vec::with_capacity(entries.len());
+    let mut index = HashMap::new();
+    for (address, kind) in entries {
+        match index.get(&address) {
+            Some(&i) => if is_test(kind) && !is_test(result[i].1) {
+                result[i].1 = kind;
+            },
+            None => {
+                index.insert(address, result.len());
+                result.push((address, kind));
+            },
+        }
+    }
+    result
+}
+
+/// rustdoc compiles each doctest as a crate named `rust_out`
+fn is_doctest_function(name: &str) -> bool {
+    name.starts_with("rust_out::")
+}
+
+/// Checks if the path is inside a build script's OUT_DIR which is found at
+/// `target/<profile>/build/<package>-<hash>/out`
+fn is_out_dir(path: &Path) -> bool {
+    let components = path.components()
+                         .map(|c| c.as_os_str())
+                         .collect::<Vec<_>>();
+    components.windows(3)
+              .any(|w| w[0] == "build" && w[2] == "out")
+}
+
 fn get_addresses_from_program<R, Offset>(prog: IncompleteLineNumberProgram<R>,
                                          entries: &[(u64, LineType)],
+                                         generated: &[(u64, u64)],
                                          project: &Path,
+                                         config: &Config,
                                          result: &mut HashMap<SourceLocation, Vec<TracerData>>) -> Result<()>
     where R: Reader<Offset = Offset>,
           Offset: ReaderOffset
 {
     let ( cprog, seq) = prog.sequences()?;
+    // Source file of each file entry of the header, resolving it touches the
+    // filesystem so it's only done once per entry. None if the file isn't a
+    // source file of the project.
+    let mut sources: HashMap<u64, Option<PathBuf>> = HashMap::new();
     for s in seq {
-        let mut temp_map: HashMap<SourceLocation, TracerData> = HashMap::new();
+        let mut temp_map: HashMap<SourceLocation, Vec<TracerData>> = HashMap::new();
+        // Blocks of a line told apart by their discriminator in this sequence
+        let mut blocks = HashSet::new();
         let mut sm = cprog.resume_from(&s);
          while let Ok(Some((header, &ln_row))) = sm.next_row() {
              // If this row isn't useful move on
             if !ln_row.is_stmt() || ln_row.line().is_none() {
                 continue;
             }
-            if let Some(file) = ln_row.file(header) {
+            let path = sources.entry(ln_row.file_index()).or_insert_with(|| {
+                let file = ln_row.file(header)?;
                 let mut path = project.to_path_buf();
                 if let Some(dir) = file.directory(header) {
                     if let Ok(temp) = dir.to_string() {
                         path.push(temp.as_ref());
                     }
                 }
-                
-                if let Ok(p) = path.canonicalize() {
-                    path = p;
-                }
+                path = resolve_source(&path, project);
                 // Fix relative paths and determine if in target directory
                 // Source in target directory shouldn't be covered as it's either
                 // autogenerated or resulting from the projects Cargo.lock
@@ -157,111 +422,246 @@ fn get_addresses_from_program<R, Offset>(prog: IncompleteLineNumberProgram<R>,
                 } else {
                     path.starts_with(project.join("target"))
                 };
-                
-                // Source is part of project so we cover it.
-                if !is_target && path.starts_with(project) {
-                    if let Some(file) = ln_row.file(header) {
-                        let line = ln_row.line().unwrap();
-                        let file = file.path_name();
-                        if let Ok(file) = file.to_string() {
-                            path.push(file.as_ref());
-                            if !path.is_file() {
-                                // Not really a source file!
-                                continue;
-                            }
-                            let address = ln_row.address();
-                            let desc = entries.iter()
-                                              .filter(|&&(addr, _)| addr == address )
-                                              .map(|&(_, t)| t)
-                                              .nth(0)
-                                              .unwrap_or(LineType::Unknown);
-                            let loc = SourceLocation {
-                                path,
-                                line,
-                            };
-                            if desc != LineType::TestMain && !temp_map.contains_key(&loc) {
-                                temp_map.insert(loc, TracerData {
-                                    address: Some(address),
-                                    trace_type: desc,
-                                    length: 1,
-                                });
-                            }
-                        }
-                    }
+                let is_target = is_target && !(config.include_out_dir && is_out_dir(&path));
+                // Only source that's part of project is covered
+                if is_target || !path.starts_with(project) {
+                    return None;
+                }
+                let name = file.path_name().to_string().ok()?.into_owned();
+                path.push(name);
+                // Modules included with `#[path]` can have file names such as
+                // `../shared/impl.rs`
+                path = resolve_source(&path, project);
+                if path.starts_with(project) && path.is_file() {
+                    Some(path)
+                } else {
+                    // Not really a source file!
+                    None
+                }
+            }).clone();
+            let path = match path {
+                Some(p) => p,
+                None => continue,
+            };
+            let line = ln_row.line().unwrap();
+            let address = ln_row.address();
+            let desc = entries.iter()
+                              .filter(|&&(addr, _)| addr == address )
+                              .map(|&(_, t)| t)
+                              .nth(0)
+                              .unwrap_or(LineType::Unknown);
+            let loc = SourceLocation {
+                path,
+                line,
+            };
+            // Rows of the generated main past its entry aren't function
+            // entries so check its range
+            let in_main = generated.iter()
+                                   .any(|&(a, l)| address >= a && address - a < l);
+            if desc != LineType::TestMain && !in_main {
+                let new_block = config.branch_coverage &&
+                                blocks.insert((loc.clone(), ln_row.discriminator()));
+                let data = temp_map.entry(loc).or_insert_with(Vec::new);
+                // Lines are traced from their first address in a sequence
+                // unless every instruction is wanted. With branch coverage the
+                // first address of each block of the line is traced.
+                if data.is_empty() || new_block || config.granularity == Granularity::Instruction {
+                    data.push(TracerData {
+                        address: Some(address),
+                        trace_type: desc,
+                        length: 1,
+                    });
                 }
             }
         }
-        for (k, v) in &temp_map {
-            if result.contains_key(k) {
-                let mut x = result.get_mut(k).unwrap();
-                x.push(v.clone());
-            } else {
-                result.insert(k.clone(), vec![v.clone()]);
-            }
+        for (k, v) in temp_map {
+            result.entry(k).or_insert_with(Vec::new).extend(v);
         }
     }
     Ok(())
 }
 
 
+/// Splits `.debug_info` into its units using their initial lengths. gimli
+/// stops iterating at the first unit it can't parse, statically linked
+/// binaries such as musl ones contain units from C code that may use a newer
+/// DWARF version, so each unit is parsed separately and bad ones skipped.
+fn split_units(debug_info: &[u8], endian: RunTimeEndian) -> Vec<&[u8]> {
+    let read = |bytes: &[u8]| -> u64 {
+        match endian {
+            RunTimeEndian::Little => bytes.iter().rev().fold(0, |acc, &b| (acc << 8) | u64::from(b)),
+            RunTimeEndian::Big => bytes.iter().fold(0, |acc, &b| (acc << 8) | u64::from(b)),
+        }
+    };
+    let mut result = vec![];
+    let mut offset = 0;
+    while offset + 4 <= debug_info.len() {
+        let length = read(&debug_info[offset..offset+4]);
+        // 64-bit DWARF has an escape value followed by an 8 byte length
+        let (header, length) = if length == 0xffff_ffff {
+            if offset + 12 > debug_info.len() {
+                break;
+            }
+            (12, read(&debug_info[offset+4..offset+12]))
+        } else {
+            (4, length)
+        };
+        let end = match ((offset + header) as u64).checked_add(length) {
+            Some(end) if end <= debug_info.len() as u64 => end as usize,
+            _ => break,
+        };
+        result.push(&debug_info[offset..end]);
+        offset = end;
+    }
+    result
+}
+
+
 fn get_line_addresses(endian: RunTimeEndian,
                       project: &Path,
-                      obj: &OFile,
+                      obj: &DebugSections,
                       analysis: &HashMap<PathBuf, LineAnalysis>,
-                      config: &Config) -> Result<TraceMap>  {
+                      harness: bool,
+                      config: &Config,
+                      dump: &mut Vec<Value>) -> Result<TraceMap>  {
 
     let mut result = TraceMap::new();
     let debug_info = obj.section_data_by_name(".debug_info").unwrap_or_default();
-    let debug_info = DebugInfo::new(&debug_info, endian);
     let debug_abbrev = obj.section_data_by_name(".debug_abbrev").unwrap_or_default();
     let debug_abbrev = DebugAbbrev::new(&debug_abbrev, endian);
     let debug_strings = obj.section_data_by_name(".debug_str").unwrap_or_default();
     let debug_strings = DebugStr::new(&debug_strings, endian);
     let debug_line = obj.section_data_by_name(".debug_line").unwrap_or_default();
     let debug_line = DebugLine::new(&debug_line, endian);
+    let debug_ranges = obj.section_data_by_name(".debug_ranges").unwrap_or_default();
+    let debug_ranges = DebugRanges::new(&debug_ranges, endian);
+    let debug_rnglists = obj.section_data_by_name(".debug_rnglists").unwrap_or_default();
+    let debug_rnglists = DebugRngLists::new(&debug_rnglists, endian);
+    let range_lists = RangeLists::new(debug_ranges, debug_rnglists)?;
 
-    let mut iter = debug_info.units();
-    while let Ok(Some(cu)) = iter.next() {
+    let mut seen_files = BTreeSet::new();
+    let mut split_dwarf = false;
+    for unit in split_units(debug_info, endian) {
+        let cu = match DebugInfo::new(unit, endian).units().next() {
+            Ok(Some(cu)) => cu,
+            Ok(None) => continue,
+            Err(e) => {
+                debug!(target: LOG_TARGET, "Skipping compilation unit: {}", e);
+                continue;
+            },
+        };
         let addr_size = cu.address_size();
         let abbr = match cu.abbreviations(&debug_abbrev) {
             Ok(a) => a,
-            _ => continue,
+            Err(e) => {
+                debug!(target: LOG_TARGET, "Skipping compilation unit, its abbreviations don't parse: {}", e);
+                continue;
+            },
         };
-        let entries = get_entry_points(&cu, &abbr, &debug_strings)
+        let functions = get_entry_points(&cu, &abbr, &debug_strings, &range_lists, &obj.symbols, config);
+        if functions.iter().any(|&(_, _, ref name)| is_doctest_function(name)) {
+            // The doctest itself is named after the file it's in but its lines
+            // count from the start of the snippet not the file, only the library
+            // it links is traced.
+            debug!(target: LOG_TARGET, "Skipping the lines of the doctest snippet");
+            continue;
+        }
+        if harness && functions.iter().any(|&(_, c, _)| c == FunctionType::Test) {
+            TESTS_FOUND.store(true, Ordering::SeqCst);
+        }
+        let entries = functions
             .iter()
-            .map(|&(a, b, c)| {
-                match c {
-                    FunctionType::Test => (a, LineType::TestEntry(b)),
-                    FunctionType::Standard => (a, LineType::FunctionEntry(b)),
-                    FunctionType::Generated => (a, LineType::TestMain),
-                }
+            .flat_map(|&(ref ranges, c, _)| {
+                // Without the libtest harness there's no generated main and
+                // test functions are called by the user's own main.
+                let c = if harness { c } else { FunctionType::Standard };
+                ranges.iter().map(move |&(a, b)| {
+                    match c {
+                        FunctionType::Test => (a, LineType::TestEntry(b)),
+                        FunctionType::Standard => (a, LineType::FunctionEntry(b)),
+                        FunctionType::Generated => (a, LineType::TestMain),
+                    }
+                })
             }).collect::<Vec<_>>();
+        let entries = fold_entries(entries);
+        let generated = functions
+            .iter()
+            .filter(|&&(_, c, _)| harness && c == FunctionType::Generated)
+            .flat_map(|&(ref ranges, _, _)| ranges.iter().cloned())
+            .collect::<Vec<_>>();
 
         if let Ok(Some((_, root))) = cu.entries(&abbr).next_dfs() {
+            let cu_name = root.attr_value(DW_AT_name)
+                              .ok()
+                              .and_then(|a| a)
+                              .and_then(|a| a.string_value(&debug_strings))
+                              .and_then(|r| r.to_string().map(|s| s.to_string()).ok())
+                              .unwrap_or_else(|| "<unknown>".to_string());
+            // Skeleton units of split DWARF name the .dwo file with the rest
+            // of the unit, only the line program is in the binary
+            split_dwarf |= [DW_AT_dwo_name, DW_AT_GNU_dwo_name].iter()
+                                                               .any(|&a| root.attr(a).ok().and_then(|a| a).is_some());
             let offset = match root.attr_value(DW_AT_stmt_list) {
                 Ok(Some(AttributeValue::DebugLineRef(o))) => o,
                 _ => continue,
             };
-            let prog = debug_line.program(offset, addr_size, None, None)?;
+            let prog = match debug_line.program(offset, addr_size, None, None) {
+                Ok(prog) => prog,
+                Err(e) => {
+                    warn!(target: LOG_TARGET, "Skipping line program of {}: {}", cu_name, e);
+                    continue;
+                },
+            };
             let mut temp_map : HashMap<SourceLocation, Vec<TracerData>> = HashMap::new();
-            if let Err(e) = get_addresses_from_program(prog, &entries, project, &mut temp_map) {
-                if config.verbose {
-                    println!("Potential issue reading test addresses {}", e);
-                }
+            if let Err(e) = get_addresses_from_program(prog, &entries, &generated, project, config, &mut temp_map) {
+                warn!(target: LOG_TARGET, "Potential issue reading the line program of {}: {}", cu_name, e);
             }
             else {
+                let before_dedup = if config.dump_traces.is_some() {
+                    dump_tracer_data(&temp_map)
+                } else {
+                    Value::Null
+                };
                 // Deduplicate addresses
                 for v in temp_map.values_mut() {
                     v.dedup_by_key(|x| x.address);
                 }
-                let temp_map = temp_map.into_iter()
-                                       .filter(|&(ref k, _)| !(config.ignore_tests && k.path.starts_with(project.join("tests"))))
-                                       .filter(|&(ref k, _)| !(config.exclude_path(&k.path)))
-                                       .filter(|&(ref k, _)| !analysis.should_ignore(k.path.as_ref(), &(k.line as usize)))
-                                       .collect::<HashMap<SourceLocation, Vec<TracerData>>>();
+                if config.dump_traces.is_some() {
+                    dump.push(json!({
+                        "unit": cu_name,
+                        "before_dedup": before_dedup,
+                        "after_dedup": dump_tracer_data(&temp_map),
+                    }));
+                }
                 let mut tracemap = TraceMap::new();
                 for (k, val) in &temp_map {
+                    seen_files.insert(k.path.clone());
+                    let skipped = if config.ignore_tests && k.path.starts_with(project.join("tests")) {
+                        Some("skipped, in tests directory with --ignore-tests")
+                    } else if config.exclude_path(&k.path) {
+                        Some("skipped, matches --exclude-files")
+                    } else if analysis.get(&k.path).map_or(false, |a| a.const_evaluated.contains(&(k.line as usize))) {
+                        Some("skipped, in a const fn only evaluated at compile time")
+                    } else if analysis.should_ignore(k.path.as_ref(), &(k.line as usize)) {
+                        Some("skipped, ignored by source analysis")
+                    } else {
+                        None
+                    };
+                    if config.explain {
+                        for v in val.iter() {
+                            explain_trace(k, v, &functions, &cu_name, skipped.unwrap_or("traced"), config);
+                        }
+                    }
+                    if skipped.is_some() {
+                        continue;
+                    }
                     for v in val.iter() {
+                        let module = v.address
+                                      .and_then(|a| find_function(&functions, a))
+                                      .and_then(|&(_, _, ref name)| module_path(name));
+                        if let Some(module) = module {
+                            tracemap.set_module(&k.path, k.line, &module);
+                        }
                         tracemap.add_trace(&k.path, Trace {
                             line: k.line,
                             address: v.address,
@@ -275,6 +675,11 @@ fn get_line_addresses(endian: RunTimeEndian,
         }
     }
 
+    if split_dwarf {
+        println!("Warning: the debug info is split into .dwo or .dwp files which aren't read, \
+                  function entries and tests may be missing from the coverage");
+    }
+
     for (file, ref line_analysis) in analysis.iter() {
         if config.exclude_path(file) {
             continue;
@@ -282,6 +687,10 @@ fn get_line_addresses(endian: RunTimeEndian,
         for line in &line_analysis.cover {
             let line = *line as u64;
             if !result.contains_location(file, line) && !line_analysis.should_ignore(line as usize) {
+                if config.explain {
+                    println!("{}:{} address: none, added by source analysis as it has no debug line entry",
+                             config.strip_project_path(file).display(), line);
+                }
                 result.add_trace(file, Trace {
                     line,
                     address: None,
@@ -291,29 +700,839 @@ fn get_line_addresses(endian: RunTimeEndian,
             }
         }
     }
+    if config.verbose {
+        let traced = result.files().into_iter().cloned().collect::<BTreeSet<_>>();
+        for file in seen_files.difference(&traced) {
+            println!("excluded {} ({})", config.strip_project_path(file).display(),
+                     exclusion_reason(file, project, analysis, config));
+        }
+    }
     Ok(result)
 }
 
-pub fn generate_tracemap(project: &Workspace, test: &Path, config: &Config) -> io::Result<TraceMap> {
-    let manifest = project.root();
+/// Explains why none of the lines of a file in the debug info were traced
+fn exclusion_reason(file: &Path,
+                    project: &Path,
+                    analysis: &HashMap<PathBuf, LineAnalysis>,
+                    config: &Config) -> String {
+    if config.ignore_tests && file.starts_with(project.join("tests")) {
+        "in the tests directory with --ignore-tests".to_string()
+    } else if let Some(pattern) = config.excluding_pattern(file) {
+        format!("matched --exclude-files pattern '{}'", pattern)
+    } else if analysis.get(file).map_or(false, |a| a.ignore.contains(&Lines::All)) {
+        "ignored by source analysis as a test or cfg'd out module".to_string()
+    } else {
+        "every line ignored by source analysis".to_string()
+    }
+}
+
+/// Finds the function whose address ranges contain the address
+fn find_function(functions: &[FuncDesc], address: u64) -> Option<&FuncDesc> {
+    functions.iter()
+             .find(|&&(ref ranges, _, _)| {
+                 ranges.iter().any(|&(a, l)| address >= a && address - a < l)
+             })
+}
+
+/// Gets the module path from a demangled function name by removing the
+/// function name, closures and any type names following the module. Trait
+/// implementations of the form `<path::Type as Trait>::method` belong to the
+/// module of the type.
+fn module_path(name: &str) -> Option<String> {
+    let name = if name.starts_with('<') {
+        name[1..].split(" as ").next().unwrap_or("").trim_end_matches('>')
+    } else {
+        name
+    };
+    let mut segments = name.split("::")
+                           .filter(|s| !s.is_empty() && !s.starts_with('{') && !s.contains(|c: char| c == '<' || c == '>'))
+                           .collect::<Vec<_>>();
+    if !name.starts_with('<') {
+        segments.pop();
+    }
+    while segments.last().map(|s| s.starts_with(char::is_uppercase)).unwrap_or(false) {
+        segments.pop();
+    }
+    if segments.is_empty() {
+        None
+    } else {
+        Some(segments.join("::"))
+    }
+}
+
+/// Prints the provenance of a traced line: the address it was found at, how
+/// it was classified, the function containing it and its compilation unit.
+fn explain_trace(loc: &SourceLocation,
+                 data: &TracerData,
+                 functions: &[FuncDesc],
+                 cu_name: &str,
+                 outcome: &str,
+                 config: &Config) {
+    let address = data.address.unwrap_or(0);
+    let (function, reason) = match find_function(functions, address) {
+        Some(&(_, FunctionType::Test, ref name)) => (name.as_str(), "test function, in a test namespace"),
+        Some(&(_, FunctionType::Generated, ref name)) => (name.as_str(), "generated test main"),
+        Some(&(_, FunctionType::Standard, ref name)) => (name.as_str(), "standard function"),
+        None => ("<unknown>", "no function contains address"),
+    };
+    println!("{}:{} address: {:#x}, type: {:?}, function: {} ({}), cu: {}, {}",
+             config.strip_project_path(&loc.path).display(),
+             loc.line,
+             address,
+             data.trace_type,
+             function,
+             reason,
+             cu_name,
+             outcome);
+}
+
+/// Checks whether the source file belongs to the package in `package_root`
+/// rather than another crate nested inside it, such as a path dependency or
+/// vendored crate. The closest directory with a Cargo.toml owns the file.
+fn is_package_source(file: &Path, package_root: &Path) -> bool {
+    file.ancestors()
+        .skip(1)
+        .find(|dir| dir.join("Cargo.toml").is_file())
+        .map(|dir| dir == package_root)
+        .unwrap_or(false)
+}
+
+/// Finds the file containing the DWARF sections for a binary built with split
+/// debug info. Checks the `.gnu_debuglink` section, then `<binary>.debug` and
+/// finally a macOS style `.dSYM` bundle. Only companion files holding all the
+/// debug info are supported, split DWARF `.dwo` and `.dwp` files aren't read.
+fn find_split_debug_info(test: &Path, obj: &OFile) -> Option<PathBuf> {
+    let dir = test.parent().unwrap_or_else(|| Path::new(""));
+    let mut candidates = vec![];
+    if let Some(link) = obj.section_data_by_name(".gnu_debuglink") {
+        // Null terminated file name followed by padding and a CRC
+        let name = link.iter()
+                       .take_while(|&&b| b != 0)
+                       .map(|&b| b as char)
+                       .collect::<String>();
+        if !name.is_empty() {
+            candidates.push(dir.join(&name));
+            candidates.push(dir.join(".debug").join(&name));
+        }
+    }
+    let mut debug = test.as_os_str().to_os_string();
+    debug.push(".debug");
+    candidates.push(PathBuf::from(debug));
+    if let Some(name) = test.file_name() {
+        let mut dsym = test.as_os_str().to_os_string();
+        dsym.push(".dSYM");
+        candidates.push(PathBuf::from(dsym).join("Contents/Resources/DWARF").join(name));
+    }
+    candidates.into_iter().find(|p| p.is_file())
+}
+
+/// Opens the test executable and calls `f` with the DWARF sections of the
+/// object containing them, which is the executable itself unless the debug info
+/// was split into a companion file. The files are unmapped before `f` is called.
+fn with_debug_object<T, F>(test: &Path, f: F) -> ::std::result::Result<T, TracerError>
+    where F: FnOnce(&DebugSections, RunTimeEndian) -> ::std::result::Result<T, TracerError>
+{
+    let (sections, endian) = read_debug_sections(test)?;
+    f(&sections, endian)
+}
+
+/// Copies the DWARF sections out of the test executable or its split debug info
+fn read_debug_sections(test: &Path) -> ::std::result::Result<(DebugSections, RunTimeEndian), TracerError> {
     let file = File::open(test)?;
-    let file = unsafe {
-        MmapOptions::new().map(&file)?
+    let file = match unsafe { MmapOptions::new().map(&file) } {
+        Ok(file) => file,
+        Err(e) => {
+            // Companion debug info files aren't looked for here so a binary
+            // with split debug info gives NoDebugInfo
+            println!("Warning: failed to map {} ({}), reading its sections instead", test.display(), e);
+            return read_debug_sections_buffered(test);
+        },
     };
+    let debug_file;
     if let Ok(obj) = OFile::parse(&*file) {
-        let analysis = get_line_analysis(project, config);
+        let obj = if obj.section_data_by_name(".debug_info").is_some() {
+            obj
+        } else {
+            // Debug info may have been split into a companion file
+            let path = find_split_debug_info(test, &obj).ok_or(TracerError::NoDebugInfo)?;
+            let f = File::open(&path)?;
+            debug_file = match unsafe { MmapOptions::new().map(&f) } {
+                Ok(file) => file,
+                Err(e) => {
+                    println!("Warning: failed to map {} ({}), reading its sections instead", path.display(), e);
+                    return read_debug_sections_buffered(&path);
+                },
+            };
+            let debug_obj = OFile::parse(&*debug_file).map_err(|_| TracerError::UnsupportedBinary)?;
+            if debug_obj.section_data_by_name(".debug_info").is_none() {
+                return Err(TracerError::NoDebugInfo);
+            }
+            debug_obj
+        };
         let endian = if obj.is_little_endian() {
             RunTimeEndian::Little
         } else {
             RunTimeEndian::Big
         };
-        if let Ok(result) = get_line_addresses(endian, manifest, &obj, &analysis, config) {
-            Ok(result)
+        Ok((DebugSections::read(&obj), endian))
+    } else {
+        Err(TracerError::UnsupportedBinary)
+    }
+}
+
+/// Reads `size` bytes from the given offset of a file `len` bytes long, the
+/// range is checked first so a corrupt header can't cause a huge allocation
+fn read_at<R: Read + Seek>(file: &mut R, len: u64, offset: u64, size: u64) -> io::Result<Vec<u8>> {
+    if offset.checked_add(size).map_or(true, |end| end > len) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "section extends past the end of the file"));
+    }
+    if size > usize::max_value() as u64 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "section is larger than the address space"));
+    }
+    let mut data = vec![0; size as usize];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut data)?;
+    Ok(data)
+}
+
+/// Gets the null terminated string at the offset of a string table
+fn table_string(table: &[u8], offset: usize) -> &[u8] {
+    let s = table.get(offset..).unwrap_or_default();
+    &s[..s.iter().position(|&b| b == 0).unwrap_or_else(|| s.len())]
+}
+
+/// Copies the DWARF sections and function symbols out of an ELF file with
+/// buffered reads of only its section headers and the sections wanted. Used
+/// when the file can't be mapped, such as a binary larger than the address
+/// space of a 32-bit host. Binaries with split debug info aren't supported and
+/// give `NoDebugInfo`, their companion file is only read this way if it's the
+/// file that can't be mapped.
+fn read_debug_sections_buffered(path: &Path) -> ::std::result::Result<(DebugSections, RunTimeEndian), TracerError> {
+    const SHT_SYMTAB: u64 = 2;
+    // Sections without data in the file
+    const SHT_NOBITS: u64 = 8;
+    const STT_FUNC: u8 = 2;
+    // Section name table index telling it's in the first section header
+    const SHN_XINDEX: u64 = 0xffff;
+
+    let file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let mut file = BufReader::new(file);
+    let mut ident = [0u8; 16];
+    file.read_exact(&mut ident)?;
+    if &ident[..4] != b"\x7fELF" {
+        return Err(TracerError::UnsupportedBinary);
+    }
+    // Size of the address and offset fields
+    let word = match ident[4] {
+        1 => 4,
+        2 => 8,
+        _ => return Err(TracerError::UnsupportedBinary),
+    };
+    let endian = match ident[5] {
+        1 => RunTimeEndian::Little,
+        2 => RunTimeEndian::Big,
+        _ => return Err(TracerError::UnsupportedBinary),
+    };
+    let read = |bytes: &[u8]| -> u64 {
+        match endian {
+            RunTimeEndian::Little => bytes.iter().rev().fold(0, |acc, &b| (acc << 8) | u64::from(b)),
+            RunTimeEndian::Big => bytes.iter().fold(0, |acc, &b| (acc << 8) | u64::from(b)),
+        }
+    };
+    let header = read_at(&mut file, len, 0, 40 + 3 * word)?;
+    let shoff = read(&header[24 + 2 * word as usize..24 + 3 * word as usize]);
+    let shentsize = read(&header[34 + 3 * word as usize..36 + 3 * word as usize]);
+    let mut shnum = read(&header[36 + 3 * word as usize..38 + 3 * word as usize]);
+    let mut shstrndx = read(&header[38 + 3 * word as usize..40 + 3 * word as usize]);
+    let word = word as usize;
+    if shoff == 0 {
+        // No section headers so nothing to read
+        return Err(TracerError::NoDebugInfo);
+    }
+    if shentsize < 12 + 4 * word as u64 {
+        return Err(TracerError::UnsupportedBinary);
+    }
+    if shnum == 0 || shstrndx == SHN_XINDEX {
+        // With extended section numbering the values that don't fit in the
+        // ELF header are the size and link of the first section header
+        let first = read_at(&mut file, len, shoff, shentsize)?;
+        if shnum == 0 {
+            shnum = read(&first[8 + 3 * word..8 + 4 * word]);
+        }
+        if shstrndx == SHN_XINDEX {
+            shstrndx = read(&first[8 + 4 * word..12 + 4 * word]);
+        }
+    }
+
+    // Name offset, type, file offset, size and linked section of each section
+    let table_size = shentsize.checked_mul(shnum).ok_or(TracerError::UnsupportedBinary)?;
+    let headers = read_at(&mut file, len, shoff, table_size)?;
+    let headers = headers.chunks(shentsize as usize)
+                         .filter(|h| h.len() >= 16 + 4 * word)
+                         .map(|h| (read(&h[0..4]),
+                                   read(&h[4..8]),
+                                   read(&h[8 + 2 * word..8 + 3 * word]),
+                                   read(&h[8 + 3 * word..8 + 4 * word]),
+                                   read(&h[8 + 4 * word..12 + 4 * word])))
+                         .collect::<Vec<_>>();
+    let names = match headers.get(shstrndx as usize) {
+        Some(&(_, _, offset, size, _)) => read_at(&mut file, len, offset, size)?,
+        None => return Err(TracerError::UnsupportedBinary),
+    };
+
+    let mut sections = HashMap::new();
+    let mut symbols = HashMap::new();
+    for &(name, kind, offset, size, link) in &headers {
+        let name = table_string(&names, name as usize);
+        if kind == SHT_NOBITS {
+            continue;
+        }
+        if let Some(&debug) = DEBUG_SECTIONS.iter().find(|s| s.as_bytes() == name) {
+            sections.insert(debug, read_at(&mut file, len, offset, size)?);
+        } else if kind == SHT_SYMTAB {
+            let strings = match headers.get(link as usize) {
+                Some(&(_, _, offset, size, _)) => read_at(&mut file, len, offset, size)?,
+                None => continue,
+            };
+            let table = read_at(&mut file, len, offset, size)?;
+            // Name, info and value offsets differ between the 32 and 64-bit
+            // symbol layouts
+            let (entsize, info, value) = if word == 8 { (24, 4, 8..16) } else { (16, 12, 4..8) };
+            for sym in table.chunks(entsize).filter(|s| s.len() == entsize) {
+                if sym[info] & 0xf != STT_FUNC {
+                    continue;
+                }
+                let address = read(&sym[value.clone()]);
+                let name = table_string(&strings, read(&sym[0..4]) as usize);
+                if address != 0 && !name.is_empty() {
+                    symbols.insert(address, String::from_utf8_lossy(name).into_owned());
+                }
+            }
+        }
+    }
+    if !sections.contains_key(".debug_info") {
+        return Err(TracerError::NoDebugInfo);
+    }
+    Ok((DebugSections { sections, symbols }, endian))
+}
+
+/// Normalises a project root so source paths can be checked against it by
+/// prefix. The root is canonicalized if possible, otherwise `.` and `..`
+/// components are resolved lexically. Trailing separators are dropped.
+pub fn normalize_root(root: &Path) -> PathBuf {
+    root.canonicalize().unwrap_or_else(|_| normalize_lexically(root))
+}
+
+/// Resolves `.` and `..` components without touching the filesystem
+fn normalize_lexically(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+    for c in path.components() {
+        match c {
+            Component::CurDir => {},
+            Component::ParentDir => match result.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    result.pop();
+                },
+                // Nothing above the filesystem root
+                Some(Component::RootDir) | Some(Component::Prefix(_)) => {},
+                _ => result.push(".."),
+            },
+            c => result.push(c.as_os_str()),
+        }
+    }
+    result
+}
+
+/// Resolves the path of a source file or directory in the line program so its
+/// lines are attributed to the real file. Prefer the canonical path unless
+/// resolving symlinks takes a source file outside of the project.
+fn resolve_source(path: &Path, project: &Path) -> PathBuf {
+    let lexical = normalize_lexically(path);
+    match path.canonicalize() {
+        Ok(p) => if p.starts_with(project) || !lexical.starts_with(project) {
+            p
         } else {
-            Err(io::Error::new(io::ErrorKind::InvalidData, "Error while parsing"))
+            lexical
+        },
+        Err(_) => lexical,
+    }
+}
+
+pub fn generate_tracemap(project: &Workspace,
+                         package: &Package,
+                         test: &Path,
+                         harness: bool,
+                         config: &Config) -> ::std::result::Result<TraceMap, TracerError> {
+    let manifest = normalize_root(project.root());
+    let analysis = get_line_analysis(project, config);
+    with_debug_object(test, |obj, endian| {
+        obj.check_names();
+        let mut dump = vec![];
+        let mut result = get_line_addresses(endian, &manifest, obj, &analysis, harness, config, &mut dump)?;
+        if config.dump_traces.is_some() {
+            if let Ok(mut traces) = TRACE_DUMP.lock() {
+                traces.push(json!({
+                    "executable": test,
+                    "units": dump,
+                }));
+            }
+        }
+        if config.exclude_dependencies {
+            let package_root = normalize_root(package.root());
+            if config.verbose {
+                for file in result.files().into_iter().filter(|f| !is_package_source(f, &package_root)) {
+                    println!("excluded {} (belongs to another package with --exclude-dependencies)",
+                             config.strip_project_path(file).display());
+                }
+            }
+            result.retain_files(|f| is_package_source(f, &package_root));
+        }
+        Ok(result)
+    })
+}
+
+/// Parses an address in a trace plan, either a number or a hex string
+fn plan_address(value: &Value) -> Option<u64> {
+    match *value {
+        Value::Number(ref n) => n.as_u64(),
+        Value::String(ref s) => {
+            let s = s.trim_start_matches("0x");
+            u64::from_str_radix(s, 16).ok()
+        },
+        _ => None,
+    }
+}
+
+/// Loads a trace plan, a JSON list of `{"path", "line", "address"}` objects,
+/// to trace instead of the locations found in the debug info. Addresses are
+/// numbers or hex strings and relative paths are relative to the directory of
+/// the manifest.
+pub fn load_trace_plan(plan: &Path, manifest: &Path) -> ::std::result::Result<TraceMap, TracerError> {
+    let root = manifest.parent().unwrap_or_else(|| Path::new(""));
+    let file = File::open(plan)?;
+    let points: Value = serde_json::from_reader(file)
+        .map_err(|e| TracerError::TracePlan(e.to_string()))?;
+    let points = points.as_array()
+                       .ok_or_else(|| TracerError::TracePlan("expected a list of trace points".to_string()))?;
+    let mut result = TraceMap::new();
+    for (i, point) in points.iter().enumerate() {
+        let malformed = || TracerError::TracePlan(format!("trace point {} needs a path, line and address", i));
+        let path = point["path"].as_str().ok_or_else(malformed)?;
+        let line = point["line"].as_u64().ok_or_else(malformed)?;
+        let address = plan_address(&point["address"]).ok_or_else(malformed)?;
+        let path = root.join(path);
+        let path = path.canonicalize().unwrap_or(path);
+        result.add_trace(&path, Trace {
+            line,
+            address: Some(address),
+            length: 1,
+            stats: CoverageStat::Line(0),
+        });
+    }
+    Ok(result)
+}
+
+/// Gets every function described in the debug info of the object
+fn get_functions(endian: RunTimeEndian, obj: &DebugSections, config: &Config) -> Result<Vec<FuncDesc>> {
+    let mut result = vec![];
+    let debug_info = obj.section_data_by_name(".debug_info").unwrap_or_default();
+    let debug_abbrev = obj.section_data_by_name(".debug_abbrev").unwrap_or_default();
+    let debug_abbrev = DebugAbbrev::new(&debug_abbrev, endian);
+    let debug_strings = obj.section_data_by_name(".debug_str").unwrap_or_default();
+    let debug_strings = DebugStr::new(&debug_strings, endian);
+    let debug_ranges = obj.section_data_by_name(".debug_ranges").unwrap_or_default();
+    let debug_ranges = DebugRanges::new(&debug_ranges, endian);
+    let debug_rnglists = obj.section_data_by_name(".debug_rnglists").unwrap_or_default();
+    let debug_rnglists = DebugRngLists::new(&debug_rnglists, endian);
+    let range_lists = RangeLists::new(debug_ranges, debug_rnglists)?;
+
+    for unit in split_units(debug_info, endian) {
+        let cu = match DebugInfo::new(unit, endian).units().next() {
+            Ok(Some(cu)) => cu,
+            _ => continue,
+        };
+        if let Ok(abbr) = cu.abbreviations(&debug_abbrev) {
+            result.append(&mut get_entry_points(&cu, &abbr, &debug_strings, &range_lists,
+                                                &obj.symbols, config));
+        }
+    }
+    Ok(result)
+}
+
+fn is_hit(trace: &Trace) -> bool {
+    match trace.stats {
+        CoverageStat::Line(x) => x > 0,
+        _ => false,
+    }
+}
+
+/// Maps the address of every trace to the trace and its file
+fn traces_by_address(traces: &TraceMap) -> BTreeMap<u64, (&PathBuf, &Trace)> {
+    let mut result = BTreeMap::new();
+    for (file, file_traces) in traces.iter() {
+        for t in file_traces {
+            if let Some(address) = t.address {
+                result.insert(address, (file, t));
+            }
+        }
+    }
+    result
+}
+
+/// A function ran if any of the traces in its address ranges were hit
+fn function_executed(ranges: &[(u64, u64)], by_address: &BTreeMap<u64, (&PathBuf, &Trace)>) -> bool {
+    ranges.iter()
+          .any(|&(a, l)| by_address.range(a..a.saturating_add(l)).any(|(_, &(_, t))| is_hit(t)))
+}
+
+/// Collapses the line coverage of a test into one trace per function at the
+/// line of its entry, hit if any line of the function was. Tests and the
+/// generated main aren't counted, nor functions whose entry line wasn't traced.
+pub fn function_coverage(test: &Path,
+                         traces: &TraceMap,
+                         config: &Config) -> ::std::result::Result<TraceMap, TracerError> {
+    let functions = with_debug_object(test, |obj, endian| {
+        get_functions(endian, obj, config).map_err(TracerError::from)
+    })?;
+    let by_address = traces_by_address(traces);
+    let mut result = TraceMap::new();
+    for &(ref ranges, func_type, ref name) in &functions {
+        if func_type != FunctionType::Standard {
+            continue;
+        }
+        let entry = ranges.iter().map(|&(a, _)| a).min().unwrap_or(0);
+        let (file, trace) = match by_address.get(&entry) {
+            Some(&(file, trace)) if entry != 0 => (file, trace),
+            _ => continue,
+        };
+        if result.contains_location(file, trace.line) {
+            continue;
+        }
+        let hits = match trace.stats {
+            CoverageStat::Line(x) if function_executed(ranges, &by_address) => x.max(1),
+            _ => 0,
+        };
+        if let Some(module) = module_path(name) {
+            result.set_module(file, trace.line, &module);
+        }
+        result.add_trace(file, Trace {
+            line: trace.line,
+            address: Some(entry),
+            length: 1,
+            stats: CoverageStat::Line(hits),
+        });
+    }
+    Ok(result)
+}
+
+/// Gets the discriminator of every statement row, these tell apart the blocks
+/// sharing a line such as the arms of a one line if else. The first block of a
+/// line has the discriminator 0 so it's kept like any other.
+fn get_discriminators(endian: RunTimeEndian, obj: &DebugSections) -> Result<HashMap<u64, u64>> {
+    let mut result = HashMap::new();
+    let debug_info = obj.section_data_by_name(".debug_info").unwrap_or_default();
+    let debug_abbrev = obj.section_data_by_name(".debug_abbrev").unwrap_or_default();
+    let debug_abbrev = DebugAbbrev::new(&debug_abbrev, endian);
+    let debug_line = obj.section_data_by_name(".debug_line").unwrap_or_default();
+    let debug_line = DebugLine::new(&debug_line, endian);
+
+    for unit in split_units(debug_info, endian) {
+        let cu = match DebugInfo::new(unit, endian).units().next() {
+            Ok(Some(cu)) => cu,
+            _ => continue,
+        };
+        let abbr = match cu.abbreviations(&debug_abbrev) {
+            Ok(a) => a,
+            Err(_) => continue,
+        };
+        let offset = match cu.entries(&abbr).next_dfs() {
+            Ok(Some((_, root))) => match root.attr_value(DW_AT_stmt_list) {
+                Ok(Some(AttributeValue::DebugLineRef(o))) => o,
+                _ => continue,
+            },
+            _ => continue,
+        };
+        let prog = match debug_line.program(offset, cu.address_size(), None, None) {
+            Ok(prog) => prog,
+            Err(_) => continue,
+        };
+        let mut rows = prog.rows();
+        while let Ok(Some((_, &row))) = rows.next_row() {
+            if row.is_stmt() {
+                result.insert(row.address(), row.discriminator());
+            }
         }
+    }
+    Ok(result)
+}
+
+/// Lines with exactly two blocks told apart by their discriminators, such as
+/// a one line `if c { a } else { b }`, become a branch of whether each block
+/// ran. The blocks of any other line are merged back into one trace summing
+/// their hits, lines with one block are kept as they are.
+fn branches_from_blocks(traces: &TraceMap, discriminators: &HashMap<u64, u64>) -> TraceMap {
+    let mut result = TraceMap::new();
+    for (file, file_traces) in traces.iter() {
+        let mut lines: BTreeMap<u64, Vec<&Trace>> = BTreeMap::new();
+        for t in file_traces {
+            lines.entry(t.line).or_insert_with(Vec::new).push(t);
+        }
+        for (line, line_traces) in lines {
+            if let Some(module) = traces.get_module(file, line) {
+                result.set_module(file, line, module);
+            }
+            let mut blocks = BTreeMap::new();
+            for t in &line_traces {
+                if let Some(&d) = t.address.and_then(|a| discriminators.get(&a)) {
+                    *blocks.entry(d).or_insert(false) |= is_hit(t);
+                }
+            }
+            let line_hits = line_traces.iter()
+                                       .map(|t| match t.stats {
+                                           CoverageStat::Line(hits) => Some(hits),
+                                           _ => None,
+                                       })
+                                       .collect::<Option<Vec<_>>>();
+            if blocks.len() == 2 {
+                let arms = blocks.values().cloned().collect::<Vec<_>>();
+                result.add_trace(file, Trace {
+                    line,
+                    address: line_traces[0].address,
+                    length: 1,
+                    stats: CoverageStat::Branch(LogicState {
+                        been_true: arms[0],
+                        been_false: arms[1],
+                    }),
+                });
+            } else if let Some(hits) = line_hits.filter(|_| blocks.len() > 1) {
+                result.add_trace(file, Trace {
+                    line,
+                    address: line_traces[0].address,
+                    length: 1,
+                    stats: CoverageStat::Line(hits.iter().sum()),
+                });
+            } else {
+                for t in line_traces {
+                    result.add_trace(file, t.clone());
+                }
+            }
+        }
+    }
+    result
+}
+
+/// Turns the lines of a test with two blocks into branches, see
+/// `branches_from_blocks`
+pub fn discriminator_branches(test: &Path, traces: &TraceMap) -> ::std::result::Result<TraceMap, TracerError> {
+    let discriminators = with_debug_object(test, |obj, endian| {
+        get_discriminators(endian, obj).map_err(TracerError::from)
+    })?;
+    Ok(branches_from_blocks(traces, &discriminators))
+}
+
+/// Checks the entry breakpoint of every function that ran was hit after
+/// tracing the test. If the rest of a function trapped but its entry didn't
+/// the address to line mapping for the entry is likely wrong.
+pub fn audit_entry_points(test: &Path,
+                          traces: &TraceMap,
+                          config: &Config) -> ::std::result::Result<(), TracerError> {
+    let functions = with_debug_object(test, |obj, endian| {
+        get_functions(endian, obj, config).map_err(TracerError::from)
+    })?;
+    let by_address = traces_by_address(traces);
+    let mut suspicious = 0;
+    for &(ref ranges, _, ref name) in &functions {
+        let executed = function_executed(ranges, &by_address);
+        let entry = ranges.iter().map(|&(a, _)| a).min().unwrap_or(0);
+        if let Some(&(file, t)) = by_address.get(&entry) {
+            if executed && !is_hit(t) {
+                suspicious += 1;
+                println!("Audit: {} ran but its entry at {:#x} ({}:{}) never trapped",
+                         name, entry, config.strip_project_path(file).display(), t.line);
+            }
+        }
+    }
+    if suspicious == 0 {
+        println!("Audit: every executed function trapped at its entry");
     } else {
-        Err(io::Error::new(io::ErrorKind::InvalidData, "Unable to parse binary."))
+        println!("Audit: {} entry addresses look mis-mapped", suspicious);
     }
+    Ok(())
 }
 
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn folded_entries() {
+        let entries = vec![(0x10, LineType::FunctionEntry(4)),
+                           (0x20, LineType::FunctionEntry(2)),
+                           (0x10, LineType::TestEntry(4)),
+                           (0x10, LineType::FunctionEntry(4))];
+        assert_eq!(fold_entries(entries),
+                   vec![(0x10, LineType::TestEntry(4)), (0x20, LineType::FunctionEntry(2))]);
+    }
+
+    #[test]
+    fn module_paths() {
+        assert_eq!(module_path("my_crate::net::tcp::connect"), Some("my_crate::net::tcp".to_string()));
+        assert_eq!(module_path("my_crate::net::Socket::new"), Some("my_crate::net".to_string()));
+        assert_eq!(module_path("<my_crate::net::Socket as core::fmt::Debug>::fmt"),
+                   Some("my_crate::net".to_string()));
+        assert_eq!(module_path("my_crate::run::{{closure}}"), Some("my_crate".to_string()));
+        assert_eq!(module_path("main"), None);
+    }
+
+    #[test]
+    fn out_dir_detection() {
+        assert!(is_out_dir(Path::new("/project/target/debug/build/foo-1234abcd/out/gen.rs")));
+        assert!(!is_out_dir(Path::new("/project/target/debug/deps/foo.rs")));
+        assert!(!is_out_dir(Path::new("/project/src/build/out.rs")));
+    }
+
+    #[test]
+    fn trace_plan_addresses() {
+        assert_eq!(plan_address(&json!(4198400)), Some(0x401000));
+        assert_eq!(plan_address(&json!("0x401000")), Some(0x401000));
+        assert_eq!(plan_address(&json!("401000")), Some(0x401000));
+        assert_eq!(plan_address(&json!("main.rs")), None);
+        assert_eq!(plan_address(&Value::Null), None);
+    }
+
+    #[test]
+    fn normalized_roots() {
+        let root = normalize_root(Path::new("/nonexistent/project/"));
+        assert_eq!(root, PathBuf::from("/nonexistent/project"));
+        assert!(Path::new("/nonexistent/project/src/lib.rs").starts_with(&root));
+        assert!(!Path::new("/nonexistent/project/target/debug/build.rs").starts_with(root.join("src")));
+
+        let root = normalize_root(Path::new("/nonexistent/./project/sub/.."));
+        assert_eq!(root, PathBuf::from("/nonexistent/project"));
+        assert_eq!(normalize_root(Path::new("/nonexistent/../..")), PathBuf::from("/"));
+        assert_eq!(normalize_root(Path::new("../nonexistent/")), PathBuf::from("../nonexistent"));
+    }
+
+    #[test]
+    fn path_attribute_sources() {
+        let project = Path::new("/nonexistent/project");
+        assert_eq!(resolve_source(Path::new("/nonexistent/project/src/net/../../shared/impl.rs"), project),
+                   PathBuf::from("/nonexistent/project/shared/impl.rs"));
+        assert_eq!(resolve_source(Path::new("/nonexistent/project/tests/../src/lib.rs"), project),
+                   PathBuf::from("/nonexistent/project/src/lib.rs"));
+
+        let project = normalize_root(Path::new(env!("CARGO_MANIFEST_DIR")));
+        assert_eq!(resolve_source(&project.join("tests/../src/lib.rs"), &project),
+                   project.join("src/lib.rs"));
+    }
+
+    #[test]
+    fn debug_info_units() {
+        let data = [3, 0, 0, 0, 1, 2, 3, 2, 0, 0, 0, 4, 5, 9, 0, 0, 0, 6];
+        let units = split_units(&data, RunTimeEndian::Little);
+        assert_eq!(units, vec![&data[0..7], &data[7..13]]);
+        let data = [0, 0, 0, 2, 1, 2];
+        assert_eq!(split_units(&data, RunTimeEndian::Big), vec![&data[..]]);
+    }
+
+    #[test]
+    fn executed_functions() {
+        let file = PathBuf::from("src/lib.rs");
+        let mut traces = TraceMap::new();
+        for &(line, address, hits) in &[(1, 0x100, 0), (2, 0x108, 2), (5, 0x200, 0)] {
+            traces.add_trace(&file, Trace {
+                line,
+                address: Some(address),
+                length: 1,
+                stats: CoverageStat::Line(hits),
+            });
+        }
+        let by_address = traces_by_address(&traces);
+        assert!(function_executed(&[(0x100, 0x20)], &by_address));
+        assert!(!function_executed(&[(0x200, 0x20)], &by_address));
+        assert!(!function_executed(&[(u64::max_value() - 1, 0x20)], &by_address));
+    }
+
+    #[test]
+    fn pathological_addresses() {
+        let data = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0];
+        assert!(split_units(&data, RunTimeEndian::Little).is_empty());
+        let functions = vec![(vec![(u64::max_value() - 4, 0x20)], FunctionType::Standard, "end".to_string()),
+                             (vec![(0x100, 0x20)], FunctionType::Standard, "start".to_string())];
+        assert_eq!(find_function(&functions, u64::max_value()).map(|f| f.2.as_str()), Some("end"));
+        assert_eq!(find_function(&functions, 0x110).map(|f| f.2.as_str()), Some("start"));
+        assert!(find_function(&functions, 0x120).is_none());
+        assert!(find_function(&functions, 0x10).is_none());
+    }
+
+    #[test]
+    fn discriminator_blocks() {
+        let file = PathBuf::from("src/lib.rs");
+        let mut traces = TraceMap::new();
+        let rows = [(1, 0x100, 1), (1, 0x108, 0), (2, 0x110, 1), (2, 0x118, 2), (2, 0x120, 0), (3, 0x128, 3)];
+        for &(line, address, hits) in &rows {
+            traces.add_trace(&file, Trace {
+                line,
+                address: Some(address),
+                length: 1,
+                stats: CoverageStat::Line(hits),
+            });
+        }
+        // The first arm of line 1 has the discriminator 0, line 2 has three blocks
+        let discriminators = [(0x100, 0), (0x108, 1), (0x110, 0), (0x118, 1), (0x120, 2), (0x128, 0)];
+        let discriminators = discriminators.iter().cloned().collect::<HashMap<u64, u64>>();
+        let result = branches_from_blocks(&traces, &discriminators);
+        let lines = result.get_child_traces(&file);
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0].stats, CoverageStat::Branch(LogicState { been_true: true, been_false: false }));
+        assert_eq!(lines[1].stats, CoverageStat::Line(3));
+        assert_eq!(lines[2].stats, CoverageStat::Line(3));
+        assert_eq!(result.covered_in_path(&file), 3);
+        assert_eq!(result.coverable_in_path(&file), 4);
+    }
+
+    #[test]
+    fn buffered_sections() {
+        let exe = ::std::env::current_exe().unwrap();
+        let (mapped, mapped_endian) = read_debug_sections(&exe).unwrap();
+        let (buffered, buffered_endian) = read_debug_sections_buffered(&exe).unwrap();
+        assert!(mapped_endian == buffered_endian);
+        for name in DEBUG_SECTIONS {
+            assert_eq!(mapped.section_data_by_name(name), buffered.section_data_by_name(name));
+        }
+        assert!(!buffered.symbols.is_empty());
+        assert!(read_debug_sections_buffered(Path::new(file!())).is_err());
+    }
+
+    #[test]
+    fn buffered_extended_numbering() {
+        let exe = ::std::env::current_exe().unwrap();
+        let (expected, _) = read_debug_sections_buffered(&exe).unwrap();
+        let mut data = ::std::fs::read(&exe).unwrap();
+        // Offsets in the header of a 64-bit little endian ELF file
+        let le = |b: &[u8]| b.iter().rev().fold(0, |acc, &x| (acc << 8) | u64::from(x));
+        let shoff = le(&data[40..48]) as usize;
+        let shnum = le(&data[60..62]);
+        let shstrndx = le(&data[62..64]);
+        // Move the section count and name table index to the first header
+        data[60..64].copy_from_slice(&[0, 0, 0xff, 0xff]);
+        data[shoff + 32..shoff + 40].copy_from_slice(&[shnum as u8, (shnum >> 8) as u8, 0, 0, 0, 0, 0, 0]);
+        data[shoff + 40..shoff + 44].copy_from_slice(&[shstrndx as u8, (shstrndx >> 8) as u8, 0, 0]);
+        let dir = ::std::env::temp_dir().join("tarpaulin-extended-numbering");
+        ::std::fs::create_dir_all(&dir).unwrap();
+        let extended = dir.join("extended");
+        ::std::fs::write(&extended, &data).unwrap();
+        let (buffered, _) = read_debug_sections_buffered(&extended).unwrap();
+        for name in DEBUG_SECTIONS {
+            assert_eq!(expected.section_data_by_name(name), buffered.section_data_by_name(name));
+        }
+        // Sections past the end of a truncated file are an error
+        let truncated = dir.join("truncated");
+        ::std::fs::write(&truncated, &data[..shoff / 2]).unwrap();
+        assert!(read_debug_sections_buffered(&truncated).is_err());
+        let _ = ::std::fs::remove_dir_all(&dir);
+    }
+}
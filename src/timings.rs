@@ -0,0 +1,41 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+
+lazy_static! {
+    static ref TIMINGS: Mutex<Vec<(String, Duration)>> = Mutex::new(vec![]);
+}
+
+
+/// Runs the provided closure recording how long it took under the given
+/// phase name.
+pub fn time<T, F: FnOnce() -> T>(phase: &str, f: F) -> T {
+    let start = Instant::now();
+    let res = f();
+    record(phase, start.elapsed());
+    res
+}
+
+/// Records the duration of a phase of the coverage run
+pub fn record(phase: &str, duration: Duration) {
+    if let Ok(mut timings) = TIMINGS.lock() {
+        timings.push((phase.to_string(), duration));
+    }
+}
+
+/// Prints the recorded phases in the order they happened
+pub fn report() {
+    if let Ok(timings) = TIMINGS.lock() {
+        println!("Timings:");
+        let mut total = Duration::from_secs(0);
+        for &(ref phase, duration) in timings.iter() {
+            println!("{}: {:.3}s", phase, as_secs(duration));
+            total += duration;
+        }
+        println!("total: {:.3}s", as_secs(total));
+    }
+}
+
+fn as_secs(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) * 1e-9
+}
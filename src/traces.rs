@@ -136,8 +136,15 @@ pub fn amount_covered(traces: &[&Trace]) -> usize {
     result
 }
 
-pub fn coverage_percentage(traces: &[&Trace]) -> f64 {
-    (amount_covered(traces) as f64) / (amount_coverable(traces) as f64)
+/// Coverage percentage ranging from 0.0-1.0, with nothing coverable the
+/// coverage is 0.0. Every report computes its percentages with this so they
+/// agree for the same results.
+pub fn coverage_percentage(covered: usize, total: usize) -> f64 {
+    if total == 0 {
+        0.0f64
+    } else {
+        (covered as f64) / (total as f64)
+    }
 }
 
 /// Stores all the program traces mapped to files and provides an interface to
@@ -146,6 +153,9 @@ pub fn coverage_percentage(traces: &[&Trace]) -> f64 {
 pub struct TraceMap {
     /// Traces in the program mapped to the given file
     traces: BTreeMap<PathBuf, Vec<Trace>>,
+    /// Module path of the function containing each traced line, keyed by
+    /// file and then line
+    modules: BTreeMap<PathBuf, BTreeMap<u64, String>>,
 }
 
 impl TraceMap {
@@ -153,6 +163,7 @@ impl TraceMap {
     pub fn new() -> TraceMap {
         TraceMap {
             traces: BTreeMap::new(),
+            modules: BTreeMap::new(),
         }
     }
 
@@ -189,6 +200,12 @@ impl TraceMap {
                 }
             }
         }
+        for (k, lines) in &other.modules {
+            let modules = self.modules.entry(k.clone()).or_insert_with(BTreeMap::new);
+            for (line, module) in lines {
+                modules.entry(*line).or_insert_with(|| module.clone());
+            }
+        }
     }
 
     /// This will collapse duplicate Traces into a single trace. Warning this
@@ -233,6 +250,50 @@ impl TraceMap {
         }
     }
 
+    /// Removes the traces of all files which don't satisfy the predicate
+    pub fn retain_files<F>(&mut self, predicate: F) where F: Fn(&Path) -> bool {
+        let removed = self.traces.keys()
+                                 .filter(|k| !predicate(k))
+                                 .cloned()
+                                 .collect::<Vec<_>>();
+        for k in &removed {
+            self.traces.remove(k);
+            self.modules.remove(k);
+        }
+    }
+
+    /// Records the module path of the function containing the given line
+    pub fn set_module(&mut self, file: &Path, line: u64, module: &str) {
+        self.modules.entry(file.to_path_buf())
+                    .or_insert_with(BTreeMap::new)
+                    .entry(line)
+                    .or_insert_with(|| module.to_string());
+    }
+
+    /// Gets the module path of the given line. Lines not inside a known
+    /// function, such as those only found by source analysis, belong to the
+    /// module of the closest preceding line in the file.
+    pub fn get_module(&self, file: &Path, line: u64) -> Option<&str> {
+        self.modules.get(file)
+                    .and_then(|m| m.range(..line+1).next_back())
+                    .map(|(_, module)| module.as_str())
+    }
+
+    /// Returns the covered and coverable amount for each module path, traces
+    /// with no known module are grouped under `<unknown>`
+    pub fn module_coverage(&self) -> BTreeMap<String, (usize, usize)> {
+        let mut result = BTreeMap::new();
+        for (file, traces) in &self.traces {
+            for t in traces {
+                let module = self.get_module(file, t.line).unwrap_or("<unknown>");
+                let entry = result.entry(module.to_string()).or_insert((0, 0));
+                entry.0 += amount_covered(&[t]);
+                entry.1 += amount_coverable(&[t]);
+            }
+        }
+        result
+    }
+
     /// Add a trace to the tracemap for the given file
     pub fn add_trace(&mut self, file: &Path, trace: Trace) {
         if self.traces.contains_key(file) {
@@ -254,6 +315,14 @@ impl TraceMap {
             .map(|x| *x)
     }
 
+    /// Gets the file and line of the trace at an address
+    pub fn get_location(&self, address: u64) -> Option<(&Path, u64)> {
+        self.traces.iter()
+            .flat_map(|(file, traces)| traces.iter().map(move |t| (file, t)))
+            .find(|&(_, t)| t.address == Some(address))
+            .map(|(file, t)| (file.as_path(), t.line))
+    }
+
     /// Gets a mutable reference to a trace at a given address
     /// Returns None if there is no trace at that address
     pub fn get_trace_mut(&mut self, address: u64) -> Option<&mut Trace> {
@@ -333,11 +402,52 @@ impl TraceMap {
 
     /// Returns coverage percentage ranging from 0.0-1.0
     pub fn coverage_percentage(&self) -> f64 {
-        coverage_percentage(self.all_traces().as_slice())
+        coverage_percentage(self.total_covered(), self.total_coverable())
+    }
+
+    /// Returns coverage percentage of the given path ranging from 0.0-1.0
+    pub fn coverage_percentage_in_path(&self, path: &Path) -> f64 {
+        coverage_percentage(self.covered_in_path(path), self.coverable_in_path(path))
     }
 
 }
 
+/// Coverage results split by the kind of test executable that produced them.
+#[derive(Debug, Default)]
+pub struct CoverageBreakdown {
+    /// Coverage collected from unit tests, the test harnesses of the lib and
+    /// bin targets
+    pub unit: TraceMap,
+    /// Coverage collected from the integration tests in the tests directory
+    pub integration: TraceMap,
+}
+
+impl CoverageBreakdown {
+    /// Create a new empty CoverageBreakdown
+    pub fn new() -> CoverageBreakdown {
+        CoverageBreakdown {
+            unit: TraceMap::new(),
+            integration: TraceMap::new(),
+        }
+    }
+
+    /// Merges the unit and integration results into a single TraceMap
+    pub fn total(&self) -> TraceMap {
+        let mut result = self.merged();
+        result.dedup();
+        result
+    }
+
+    /// Merges the unit and integration results keeping traces of the same
+    /// line at different addresses separate
+    pub fn merged(&self) -> TraceMap {
+        let mut result = TraceMap::new();
+        result.merge(&self.unit);
+        result.merge(&self.integration);
+        result
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -461,4 +571,135 @@ mod tests {
             stats: CoverageStat::Line(7)
         }));
     }
+
+    #[test]
+    fn breakdown_total() {
+        let mut breakdown = CoverageBreakdown::new();
+        breakdown.unit.add_trace(Path::new("file.rs"), Trace {
+            line: 1,
+            address: Some(1),
+            length: 0,
+            stats: CoverageStat::Line(1)
+        });
+        breakdown.unit.add_trace(Path::new("file.rs"), Trace {
+            line: 2,
+            address: Some(2),
+            length: 0,
+            stats: CoverageStat::Line(0)
+        });
+        breakdown.integration.add_trace(Path::new("file.rs"), Trace {
+            line: 2,
+            address: Some(2),
+            length: 0,
+            stats: CoverageStat::Line(3)
+        });
+        assert_eq!(breakdown.unit.total_covered(), 1);
+        assert_eq!(breakdown.integration.total_covered(), 1);
+        let total = breakdown.total();
+        assert_eq!(total.total_coverable(), 2);
+        assert_eq!(total.total_covered(), 2);
+    }
+
+    #[test]
+    fn retain_files() {
+        let mut t1 = TraceMap::new();
+        t1.add_trace(Path::new("src/lib.rs"), Trace {
+            line: 1,
+            address: Some(1),
+            length: 0,
+            stats: CoverageStat::Line(1)
+        });
+        t1.add_trace(Path::new("dep/src/lib.rs"), Trace {
+            line: 1,
+            address: Some(2),
+            length: 0,
+            stats: CoverageStat::Line(1)
+        });
+        t1.retain_files(|f| !f.starts_with("dep"));
+        assert_eq!(t1.files(), vec![Path::new("src/lib.rs")]);
+    }
+
+    #[test]
+    fn empty_coverage_percentage() {
+        let t1 = TraceMap::new();
+        assert_eq!(t1.coverage_percentage(), 0.0f64);
+    }
+
+    #[test]
+    fn single_line_coverage_percentage() {
+        let mut t1 = TraceMap::new();
+        let file = Path::new("src/lib.rs");
+        t1.add_trace(file, Trace {
+            line: 1,
+            address: Some(1),
+            length: 0,
+            stats: CoverageStat::Line(0)
+        });
+        assert_eq!(t1.coverage_percentage(), 0.0f64);
+        assert_eq!(t1.coverage_percentage_in_path(file), 0.0f64);
+        assert_eq!(t1.coverage_percentage_in_path(Path::new("src/main.rs")), 0.0f64);
+        assert_eq!(coverage_percentage(1, 1), 1.0f64);
+        assert_eq!(coverage_percentage(1, 0), 0.0f64);
+    }
+
+
+    #[test]
+    fn module_coverage() {
+        let mut t = TraceMap::new();
+        for &(line, hits) in &[(1, 1), (2, 0), (5, 1)] {
+            t.add_trace(Path::new("lib.rs"), Trace {
+                line,
+                address: None,
+                length: 0,
+                stats: CoverageStat::Line(hits)
+            });
+        }
+        t.set_module(Path::new("lib.rs"), 1, "my_crate");
+        t.set_module(Path::new("lib.rs"), 5, "my_crate::net");
+
+        assert_eq!(t.get_module(Path::new("lib.rs"), 2), Some("my_crate"));
+        let modules = t.module_coverage();
+        assert_eq!(modules.get("my_crate"), Some(&(1, 2)));
+        assert_eq!(modules.get("my_crate::net"), Some(&(1, 1)));
+    }
+
+    #[test]
+    fn trace_locations() {
+        let mut t = TraceMap::new();
+        t.add_trace(Path::new("src/lib.rs"), Trace {
+            line: 4,
+            address: Some(0x1000),
+            length: 1,
+            stats: CoverageStat::Line(0),
+        });
+        assert_eq!(t.get_location(0x1000), Some((Path::new("src/lib.rs"), 4)));
+        assert_eq!(t.get_location(0x2000), None);
+    }
+
+    #[test]
+    fn same_file_names() {
+        let a = Path::new("src/a/mod.rs");
+        let b = Path::new("src/b/mod.rs");
+        let mut t1 = TraceMap::new();
+        t1.add_trace(a, Trace {
+            line: 1,
+            address: Some(1),
+            length: 0,
+            stats: CoverageStat::Line(1)
+        });
+        let mut t2 = TraceMap::new();
+        t2.add_trace(b, Trace {
+            line: 1,
+            address: Some(2),
+            length: 0,
+            stats: CoverageStat::Line(0)
+        });
+        t1.merge(&t2);
+        t1.dedup();
+        assert_eq!(t1.files(), vec![a, b]);
+        assert_eq!(t1.covered_in_path(a), 1);
+        assert_eq!(t1.covered_in_path(b), 0);
+        assert_eq!(t1.coverable_in_path(b), 1);
+        assert_eq!(t1.get_location(2), Some((b, 1)));
+    }
 }
@@ -0,0 +1,53 @@
+use std::ffi::OsStr;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, SystemTime};
+use walkdir::{DirEntry, WalkDir};
+use is_interrupted;
+
+
+/// How often the watched files are polled for changes. Polling the
+/// modification times avoids depending on a platform specific file
+/// notification API, only the packages' manifests and source directories are
+/// walked so each poll is cheap next to the coverage run it triggers.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Files and directories of a package that are watched, the manifest, build
+/// script and the directories holding the sources of its targets
+const PACKAGE_PATHS: &[&str] = &["Cargo.toml", "build.rs", "src", "tests", "examples", "benches"];
+
+
+fn is_watched(entry: &DirEntry) -> bool {
+    let p = entry.path();
+    p.extension() == Some(OsStr::new("rs")) || p.file_name() == Some(OsStr::new("Cargo.toml"))
+}
+
+/// Gets the paths watched for the packages with the given roots
+pub fn watched_paths(roots: &[PathBuf]) -> Vec<PathBuf> {
+    let mut paths = roots.iter()
+                         .flat_map(|root| PACKAGE_PATHS.iter().map(move |p| root.join(p)))
+                         .collect::<Vec<_>>();
+    paths.sort();
+    paths.dedup();
+    paths
+}
+
+/// Checks whether any source file or manifest under the watched paths has
+/// been modified after the given time
+fn has_changed(paths: &[PathBuf], since: SystemTime) -> bool {
+    paths.iter()
+         .filter(|p| p.exists())
+         .flat_map(|p| WalkDir::new(p).into_iter().filter_map(|e| e.ok()))
+         .filter(|e| is_watched(e))
+         .filter_map(|e| e.metadata().ok())
+         .filter_map(|m| m.modified().ok())
+         .any(|t| t > since)
+}
+
+/// Blocks until a source file or manifest under the watched paths is modified
+/// after the given time or tarpaulin is interrupted.
+pub fn wait_for_change(paths: &[PathBuf], since: SystemTime) {
+    while !(is_interrupted() || has_changed(paths, since)) {
+        thread::sleep(POLL_INTERVAL);
+    }
+}
@@ -0,0 +1,8 @@
+extern crate custom_harness;
+
+use custom_harness::double;
+
+fn main() {
+    assert_eq!(double(2), 4);
+    assert_eq!(double(0), 0);
+}
@@ -0,0 +1,8 @@
+/// Only called from its doctest
+///
+/// ```
+/// assert_eq!(doctests::double(2), 4);
+/// ```
+pub fn double(x: i32) -> i32 {
+    x * 2
+}
@@ -0,0 +1,22 @@
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+
+pub static STARTED: AtomicUsize = ATOMIC_USIZE_INIT;
+
+// Ran by the loader before main like a #[ctor] function
+extern "C" fn start() {
+    STARTED.fetch_add(1, Ordering::SeqCst);
+}
+
+#[used]
+#[link_section = ".init_array"]
+static INIT: extern "C" fn() = start;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ran_before_main() {
+        assert_eq!(STARTED.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,24 @@
+pub fn in_child() -> u32 {
+    7
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process::Command;
+
+    #[test]
+    fn runs_in_subprocess() {
+        if env::var("REEXEC_CHILD").is_ok() {
+            assert_eq!(in_child(), 7);
+        } else {
+            let status = Command::new(env::current_exe().unwrap())
+                .args(&["--exact", "tests::runs_in_subprocess"])
+                .env("REEXEC_CHILD", "1")
+                .status()
+                .unwrap();
+            assert!(status.success());
+        }
+    }
+}
@@ -0,0 +1,10 @@
+pub mod a;
+pub mod b;
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn only_a() {
+        assert_eq!(::a::double(2), 4);
+    }
+}
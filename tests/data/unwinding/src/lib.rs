@@ -0,0 +1,29 @@
+use std::thread;
+
+pub struct Guard {
+    pub released: bool,
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            self.released = true;
+        }
+    }
+}
+
+pub fn fail() {
+    let _guard = Guard { released: false };
+    panic!("failed while holding the guard");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[should_panic]
+    fn drops_while_unwinding() {
+        fail();
+    }
+}
@@ -1,8 +1,12 @@
 extern crate cargo_tarpaulin;
 
-use cargo_tarpaulin::launch_tarpaulin;
+use cargo_tarpaulin::{launch_tarpaulin, run_with_reports};
 use cargo_tarpaulin::config::Config;
+use cargo_tarpaulin::report::DynReport;
+use cargo_tarpaulin::traces::TraceMap;
 use std::env;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::time::Duration;
 
 
@@ -80,3 +84,89 @@ fn returns_expr_coverage() {
 fn loops_expr_coverage() {
     check_percentage("loops", 1.0f64, true);
 }
+
+#[test]
+fn symlinked_src_coverage() {
+    check_percentage("symlinks", 0.0f64, true);
+}
+
+#[test]
+fn custom_harness_coverage() {
+    check_percentage("custom_harness", 1.0f64, true);
+}
+
+#[test]
+fn reexec_coverage() {
+    check_percentage("reexec", 1.0f64, true);
+}
+
+#[test]
+fn unwinding_coverage() {
+    // The guard is only dropped while unwinding from the panic
+    check_percentage("unwinding", 1.0f64, true);
+}
+
+#[test]
+fn pre_main_coverage() {
+    // Constructors run before main so are only covered if the breakpoints are
+    // in place when the test stops at the exec trap
+    check_percentage("pre_main", 1.0f64, true);
+}
+
+#[test]
+fn same_file_names() {
+    let mut config = Config::default();
+    config.test_timeout = Duration::from_secs(60);
+    let mut test_dir = env::current_dir().unwrap();
+    test_dir.push("tests");
+    test_dir.push("data");
+    test_dir.push("same_names");
+    config.manifest = test_dir.join("Cargo.toml");
+
+    let (res, _) = launch_tarpaulin(&config).unwrap();
+    // Only a's mod.rs is tested, b's shouldn't share its results
+    let a = test_dir.join("src").join("a").join("mod.rs");
+    let b = test_dir.join("src").join("b").join("mod.rs");
+    assert!(res.coverable_in_path(&a) > 0);
+    assert_eq!(res.covered_in_path(&a), res.coverable_in_path(&a));
+    assert!(res.coverable_in_path(&b) > 0);
+    assert_eq!(res.covered_in_path(&b), 0);
+}
+
+#[test]
+fn custom_report() {
+    let mut config = Config::default();
+    config.test_timeout = Duration::from_secs(60);
+    let mut test_dir = env::current_dir().unwrap();
+    test_dir.push("tests");
+    test_dir.push("data");
+    test_dir.push("simple_project");
+    config.manifest = test_dir.join("Cargo.toml");
+
+    let coverable = Arc::new(AtomicUsize::new(0));
+    let reported = coverable.clone();
+    let report: Box<DynReport> = Box::new(move |coverage: &TraceMap, _: &Config| {
+        reported.store(coverage.total_coverable(), Ordering::SeqCst);
+    });
+    let _ = run_with_reports(&config, &[report]);
+    assert!(coverable.load(Ordering::SeqCst) > 0);
+}
+
+#[test]
+fn doctest_coverage() {
+    let mut config = Config::default();
+    config.test_timeout = Duration::from_secs(60);
+    config.doc = true;
+    let mut test_dir = env::current_dir().unwrap();
+    test_dir.push("tests");
+    test_dir.push("data");
+    test_dir.push("doctests");
+    config.manifest = test_dir.join("Cargo.toml");
+
+    // The library is only called from its doctest
+    let (res, tp) = launch_tarpaulin(&config).unwrap();
+    assert!(tp);
+    let lib = test_dir.join("src").join("lib.rs");
+    assert!(res.coverable_in_path(&lib) > 0);
+    assert_eq!(res.covered_in_path(&lib), res.coverable_in_path(&lib));
+}